@@ -0,0 +1,553 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::elf::{Object, SHF_EXECINSTR, STB_LOCAL, STT_FUNC};
+
+/// Size of a BPF instruction. `lddw` takes two.
+pub(crate) const BPF_INSN_SIZE: u64 = 8;
+/// The verifier rounds every stack frame up to this.
+const STACK_FRAME_ALIGN: u64 = 32;
+/// The read-only frame pointer register.
+const BPF_REG_FP: u8 = 10;
+
+// instruction classes
+const BPF_LD: u8 = 0x00;
+const BPF_LDX: u8 = 0x01;
+const BPF_ST: u8 = 0x02;
+const BPF_STX: u8 = 0x03;
+const BPF_ALU: u8 = 0x04;
+const BPF_JMP: u8 = 0x05;
+const BPF_JMP32: u8 = 0x06;
+const BPF_ALU64: u8 = 0x07;
+// ALU operations and operand source
+const BPF_ADD: u8 = 0x00;
+const BPF_SUB: u8 = 0x10;
+const BPF_MOV: u8 = 0xb0;
+const BPF_X: u8 = 0x08;
+/// The mode of atomic operations, which are stores.
+const BPF_ATOMIC: u8 = 0xc0;
+/// The atomic operation adding without fetching, the only one old kernels support.
+const BPF_ATOMIC_ADD: i64 = 0x00;
+// jump operations that don't branch
+const BPF_JA: u8 = 0x00;
+const BPF_EXIT: u8 = 0x90;
+/// `lddw`, the two instructions wide 64 bit immediate load.
+const BPF_LD_IMM64: u8 = 0x18;
+/// `call`. The source register tells helper, BPF to BPF and kfunc calls apart.
+const BPF_CALL: u8 = 0x85;
+const BPF_PSEUDO_CALL: u8 = 1;
+
+/// The stack used by the deepest call chain of a program.
+pub(crate) struct ProgramStack {
+    pub(crate) name: String,
+    /// The sum of the frames of the chain, each rounded up like the verifier does.
+    pub(crate) depth: u64,
+    /// The functions of the chain, starting with the program, with the size of their frame.
+    pub(crate) chain: Vec<(String, u64)>,
+}
+
+/// The instructions of a program and of the functions it calls.
+pub(crate) struct ProgramSize {
+    pub(crate) name: String,
+    /// Instruction slots, `lddw` counting as two like the verifier does.
+    pub(crate) insns: u64,
+    /// Conditional jumps.
+    pub(crate) branches: u64,
+    /// Backward jumps.
+    pub(crate) loops: u64,
+    /// BPF to BPF calls.
+    pub(crate) calls: u64,
+    /// Atomic operations other than add.
+    pub(crate) atomics: u64,
+}
+
+struct Function {
+    name: String,
+    /// Index of the function's symbol in [`Object::symbols`].
+    symbol: usize,
+    section: usize,
+    start: u64,
+    end: u64,
+    global: bool,
+    frame_size: u64,
+    branches: u64,
+    loops: u64,
+    atomics: u64,
+    /// Indices of the functions this one calls.
+    calls: Vec<usize>,
+}
+
+// what `analyze` finds in the code of a function
+struct Code {
+    frame_size: u64,
+    /// Index and immediate of the BPF to BPF calls.
+    calls: Vec<(u64, i32)>,
+    branches: u64,
+    loops: u64,
+    atomics: u64,
+}
+
+/// Returns the deepest call chain of every global function of `object`, which must be a BPF
+/// object. Frame sizes are found from the frame pointer offsets the functions access.
+pub(crate) fn stack_usage(object: &Object) -> Vec<ProgramStack> {
+    let functions = functions(object);
+    let mut deepest = vec![None; functions.len()];
+    let mut visiting = vec![false; functions.len()];
+    functions
+        .iter()
+        .enumerate()
+        .filter(|(_, function)| function.global)
+        .map(|(index, function)| {
+            let (depth, chain, _) = deepest_chain(&functions, index, &mut deepest, &mut visiting);
+            ProgramStack {
+                name: function.name.clone(),
+                depth,
+                chain: chain
+                    .into_iter()
+                    .map(|index| (functions[index].name.clone(), functions[index].frame_size))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Returns the size of every global function of `object`, which must be a BPF object, counting
+/// each function it can call once.
+pub(crate) fn program_sizes(object: &Object) -> Vec<ProgramSize> {
+    let functions = functions(object);
+    functions
+        .iter()
+        .enumerate()
+        .filter(|(_, function)| function.global)
+        .map(|(index, function)| {
+            let mut reachable = vec![false; functions.len()];
+            let mut stack = vec![index];
+            while let Some(index) = stack.pop() {
+                if !reachable[index] {
+                    reachable[index] = true;
+                    stack.extend(&functions[index].calls);
+                }
+            }
+
+            let mut size = ProgramSize {
+                name: function.name.clone(),
+                insns: 0,
+                branches: 0,
+                loops: 0,
+                calls: 0,
+                atomics: 0,
+            };
+            for function in reachable
+                .iter()
+                .zip(&functions)
+                .filter_map(|(reachable, function)| reachable.then_some(function))
+            {
+                size.insns += (function.end - function.start) / BPF_INSN_SIZE;
+                size.branches += function.branches;
+                size.loops += function.loops;
+                size.calls += function.calls.len() as u64;
+                size.atomics += function.atomics;
+            }
+            size
+        })
+        .collect()
+}
+
+/// Returns the indices in [`Object::symbols`] of the functions the global functions of
+/// `object`, which must be a BPF object, call directly or not.
+pub(crate) fn called_functions(object: &Object) -> HashSet<usize> {
+    let functions = functions(object);
+    let mut called = HashSet::new();
+    let mut stack = functions
+        .iter()
+        .filter(|function| function.global)
+        .flat_map(|function| function.calls.iter().copied())
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        if called.insert(functions[index].symbol) {
+            stack.extend(&functions[index].calls);
+        }
+    }
+
+    called
+}
+
+// returns the functions of `object` with what their code does and whom they call
+fn functions(object: &Object) -> Vec<Function> {
+    let mut functions = object
+        .symbols
+        .iter()
+        .enumerate()
+        .filter_map(|(index, symbol)| {
+            let section = symbol.section_index()?;
+            (symbol.kind() == STT_FUNC && object.sections.get(section)?.flags & SHF_EXECINSTR != 0)
+                .then(|| Function {
+                    name: symbol.name.clone(),
+                    symbol: index,
+                    section,
+                    start: symbol.value,
+                    end: symbol.value + symbol.size,
+                    global: symbol.binding() != STB_LOCAL,
+                    frame_size: 0,
+                    branches: 0,
+                    loops: 0,
+                    atomics: 0,
+                    calls: Vec::new(),
+                })
+        })
+        .collect::<Vec<_>>();
+
+    // section -> offset of the instruction -> symbol the relocation refers to
+    let mut relocations = HashMap::new();
+    for section in &object.sections {
+        if let Some(target) = section.relocated_section() {
+            relocations.insert(
+                target,
+                section
+                    .relocations
+                    .iter()
+                    .map(|r| (r.offset, r.symbol as usize))
+                    .collect::<HashMap<_, _>>(),
+            );
+        }
+    }
+    let find = |functions: &[Function], section: usize, offset: u64| {
+        functions
+            .iter()
+            .position(|f| f.section == section && (f.start..f.end).contains(&offset))
+    };
+
+    for index in 0..functions.len() {
+        let function = &functions[index];
+        let data = &object.sections[function.section].data;
+        let code = data
+            .get(function.start as usize..function.end as usize)
+            .unwrap_or_default();
+        let code = analyze(code, object.little_endian);
+
+        let mut callees = Vec::new();
+        for &(insn, imm) in &code.calls {
+            let offset = function.start + insn * BPF_INSN_SIZE;
+            // calls to other sections are relocated, libbpf resolves them to the instruction at
+            // the symbol plus the immediate
+            let (section, base) = match relocations
+                .get(&function.section)
+                .and_then(|relocations| relocations.get(&offset))
+                .and_then(|symbol| object.symbols.get(*symbol))
+            {
+                Some(symbol) => match symbol.section_index() {
+                    Some(section) => (section, symbol.value),
+                    None => continue,
+                },
+                None => (function.section, offset),
+            };
+            let target = base as i64 + (imm as i64 + 1) * BPF_INSN_SIZE as i64;
+            if let Some(callee) = u64::try_from(target)
+                .ok()
+                .and_then(|target| find(&functions, section, target))
+            {
+                callees.push(callee);
+            }
+        }
+
+        let function = &mut functions[index];
+        function.frame_size = code.frame_size;
+        function.branches = code.branches;
+        function.loops = code.loops;
+        function.atomics = code.atomics;
+        function.calls = callees;
+    }
+
+    functions
+}
+
+// returns the depth and functions of the deepest call chain starting at `index`, and whether a
+// recursive call was cut. Recursive calls aren't followed, so a chain found while a caller of
+// `index` is being visited depends on that caller and isn't remembered in `deepest`.
+fn deepest_chain(
+    functions: &[Function],
+    index: usize,
+    deepest: &mut Vec<Option<(u64, Vec<usize>)>>,
+    visiting: &mut Vec<bool>,
+) -> (u64, Vec<usize>, bool) {
+    if let Some((depth, chain)) = &deepest[index] {
+        return (*depth, chain.clone(), false);
+    }
+    if visiting[index] {
+        return (0, Vec::new(), true);
+    }
+    visiting[index] = true;
+    let mut longest = (0, Vec::new());
+    let mut cut = false;
+    for &callee in &functions[index].calls {
+        let (depth, chain, callee_cut) = deepest_chain(functions, callee, deepest, visiting);
+        cut |= callee_cut;
+        if depth > longest.0 {
+            longest = (depth, chain);
+        }
+    }
+    visiting[index] = false;
+
+    let frame = functions[index].frame_size.max(1);
+    let (depth, mut chain) = longest;
+    chain.insert(0, index);
+    let depth = depth + frame.div_ceil(STACK_FRAME_ALIGN) * STACK_FRAME_ALIGN;
+    if !cut {
+        deepest[index] = Some((depth, chain.clone()));
+    }
+
+    (depth, chain, cut)
+}
+
+// returns the stack frame size of the function with instructions `code`, its BPF to BPF calls,
+// its conditional and backward jumps and its atomic operations
+fn analyze(code: &[u8], little_endian: bool) -> Code {
+    let mut frame_size = 0;
+    let mut calls = Vec::new();
+    let (mut branches, mut loops, mut atomics) = (0, 0, 0);
+    // the registers holding frame pointer relative addresses, with their offset
+    let mut fp_offsets = [None::<i64>; 11];
+    fp_offsets[BPF_REG_FP as usize] = Some(0);
+    let mut access = |offset: Option<i64>| {
+        if let Some(offset) = offset.filter(|offset| *offset < 0) {
+            frame_size = frame_size.max(offset.unsigned_abs());
+        }
+    };
+
+    let mut index = 0;
+    let size = BPF_INSN_SIZE as usize;
+    while let Some(insn) = code.get(index * size..(index + 1) * size) {
+        let opcode = insn[0];
+        let (dst, src) = if little_endian {
+            (insn[1] & 0xf, insn[1] >> 4)
+        } else {
+            (insn[1] >> 4, insn[1] & 0xf)
+        };
+        let (off, imm) = if little_endian {
+            (
+                i16::from_le_bytes([insn[2], insn[3]]),
+                i32::from_le_bytes([insn[4], insn[5], insn[6], insn[7]]),
+            )
+        } else {
+            (
+                i16::from_be_bytes([insn[2], insn[3]]),
+                i32::from_be_bytes([insn[4], insn[5], insn[6], insn[7]]),
+            )
+        };
+        let (dst, src) = (dst as usize % 11, src as usize % 11);
+        let (off, imm) = (off as i64, imm as i64);
+
+        match opcode & 0x07 {
+            BPF_LDX => {
+                access(fp_offsets[src].map(|base| base + off));
+                fp_offsets[dst] = None;
+            }
+            BPF_STX if opcode & 0xe0 == BPF_ATOMIC => {
+                access(fp_offsets[dst].map(|base| base + off));
+                if imm != BPF_ATOMIC_ADD {
+                    atomics += 1;
+                    // fetching operations load the old value into src, or r0 for cmpxchg
+                    fp_offsets[src] = None;
+                    fp_offsets[0] = None;
+                }
+            }
+            BPF_ST | BPF_STX => access(fp_offsets[dst].map(|base| base + off)),
+            BPF_ALU64 if opcode == BPF_ALU64 | BPF_MOV | BPF_X => {
+                fp_offsets[dst] = fp_offsets[src];
+            }
+            BPF_ALU64 if opcode == BPF_ALU64 | BPF_ADD => {
+                fp_offsets[dst] = fp_offsets[dst].map(|base| base + imm);
+                access(fp_offsets[dst]);
+            }
+            BPF_ALU64 if opcode == BPF_ALU64 | BPF_SUB => {
+                fp_offsets[dst] = fp_offsets[dst].map(|base| base - imm);
+                access(fp_offsets[dst]);
+            }
+            BPF_ALU | BPF_ALU64 => fp_offsets[dst] = None,
+            BPF_LD if opcode == BPF_LD_IMM64 => {
+                fp_offsets[dst] = None;
+                index += 1;
+            }
+            BPF_JMP if opcode == BPF_CALL => {
+                if src as u8 == BPF_PSEUDO_CALL {
+                    calls.push((index as u64, imm as i32));
+                }
+                // calls clobber r0 to r5
+                fp_offsets[..6].fill(None);
+            }
+            BPF_JMP | BPF_JMP32 if opcode & 0xf0 != BPF_EXIT => {
+                if opcode & 0xf0 != BPF_JA {
+                    branches += 1;
+                }
+                if off < 0 {
+                    loops += 1;
+                }
+            }
+            _ => {}
+        }
+        // r10 can't be written
+        fp_offsets[BPF_REG_FP as usize] = Some(0);
+        index += 1;
+    }
+
+    Code {
+        frame_size,
+        calls,
+        branches,
+        loops,
+        atomics,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // see tests/inputs/stack.ll
+    const STACK: &[u8] = include_bytes!("../tests/inputs/stack.o");
+
+    #[test]
+    fn test_stack_usage() {
+        let object = Object::parse(STACK).unwrap();
+        let programs = stack_usage(&object);
+        let chains = programs
+            .iter()
+            .map(|program| (program.name.as_str(), program.depth, program.chain.clone()))
+            .collect::<Vec<_>>();
+        let chain = |functions: &[(&str, u64)]| {
+            functions
+                .iter()
+                .map(|(name, size)| (name.to_string(), *size))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            chains,
+            [
+                (
+                    "prog",
+                    // 256 + 304 rounded up to 320 + 32 for `leaf`, which has no frame
+                    608,
+                    chain(&[("prog", 256), ("helper", 304), ("leaf", 0)])
+                ),
+                ("small", 64, chain(&[("small", 8), ("leaf", 0)])),
+                ("counter", 64, chain(&[("counter", 0), ("leaf", 0)])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deepest_chain_recursion() {
+        let function = |name: &str, frame_size, calls: Vec<usize>| Function {
+            name: name.to_string(),
+            symbol: 0,
+            section: 0,
+            start: 0,
+            end: 0,
+            global: true,
+            frame_size,
+            branches: 0,
+            loops: 0,
+            atomics: 0,
+            calls,
+        };
+        // `a` and `b` call each other. The chain of `b` found while visiting `a` stops before
+        // `a`, it mustn't be reused for `b` itself.
+        let functions = vec![function("a", 64, vec![1]), function("b", 8, vec![0])];
+        let mut deepest = vec![None; functions.len()];
+        let mut visiting = vec![false; functions.len()];
+        let (depth, chain, _) = deepest_chain(&functions, 0, &mut deepest, &mut visiting);
+        assert_eq!((depth, chain), (96, vec![0, 1]));
+        let (depth, chain, _) = deepest_chain(&functions, 1, &mut deepest, &mut visiting);
+        assert_eq!((depth, chain), (96, vec![1, 0]));
+    }
+
+    #[test]
+    fn test_program_sizes() {
+        let object = Object::parse(STACK).unwrap();
+        let sizes = program_sizes(&object)
+            .into_iter()
+            .map(|size| {
+                (
+                    size.name,
+                    size.insns,
+                    size.branches,
+                    size.loops,
+                    size.calls,
+                    size.atomics,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [
+                // `helper` and `leaf` are 6 and 2 instructions
+                ("prog".to_string(), 14, 0, 0, 2, 0),
+                ("small".to_string(), 8, 0, 0, 1, 0),
+                ("counter".to_string(), 17, 2, 1, 1, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_called_functions() {
+        let object = Object::parse(STACK).unwrap();
+        let mut called = called_functions(&object)
+            .into_iter()
+            .map(|index| object.symbols[index].name.as_str())
+            .collect::<Vec<_>>();
+        called.sort();
+        assert_eq!(called, ["helper", "leaf"]);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let insn = |code: u8, regs: u8, off: i16, imm: i32| {
+            let mut insn = vec![code, regs];
+            insn.extend(off.to_le_bytes());
+            insn.extend(imm.to_le_bytes());
+            insn
+        };
+        let code = [
+            // r2 = r10
+            insn(0xbf, 0xa2, 0, 0),
+            // r2 -= 16
+            insn(0x17, 0x02, 0, 16),
+            // *(u64 *)(r2 - 24) = r1, 40 bytes below the frame pointer
+            insn(0x7b, 0x12, -24, 0),
+            // call 2, a BPF to BPF call
+            insn(0x85, 0x10, 0, 2),
+            // lddw r2, 0 takes two slots
+            insn(0x18, 0x02, 0, 0),
+            insn(0x00, 0x00, 0, 0),
+            // *(u64 *)(r2 - 100) = r1, r2 isn't frame pointer relative anymore
+            insn(0x7b, 0x12, -100, 0),
+            // call 1, a helper
+            insn(0x85, 0x00, 0, 1),
+            // exit
+            insn(0x95, 0x00, 0, 0),
+        ]
+        .concat();
+        let code = analyze(&code, true);
+        assert_eq!((code.frame_size, code.calls), (40, vec![(3, 2)]));
+        assert_eq!((code.branches, code.loops), (0, 0));
+
+        let code = [
+            // if r1 == 0 goto +3
+            insn(0x15, 0x01, 3, 0),
+            // r1 += -1
+            insn(0x07, 0x01, 0, -1),
+            // if w1 < w2 goto -2, a 32 bit backward branch
+            insn(0xae, 0x21, -2, 0),
+            // goto -3, a backward jump that doesn't branch
+            insn(0x05, 0x00, -3, 0),
+            // lock *(u64 *)(r1 + 0) += r2, supported by every kernel
+            insn(0xdb, 0x21, 0, 0x00),
+            // r2 = atomic64_fetch_add((u64 *)(r1 + 0), r2)
+            insn(0xdb, 0x21, 0, 0x01),
+            // exit
+            insn(0x95, 0x00, 0, 0),
+        ]
+        .concat();
+        let code = analyze(&code, true);
+        assert_eq!((code.branches, code.loops, code.atomics), (2, 2, 1));
+    }
+}
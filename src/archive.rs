@@ -0,0 +1,190 @@
+use std::{collections::HashMap, str};
+
+const GLOBAL_HEADER: &[u8] = b"!<arch>\n";
+const HEADER_LEN: usize = 60;
+
+/// Reads the symbol table of an `ar` archive.
+///
+/// Returns a map from symbol name to the index of the archive member that defines it. Member
+/// indices count regular members only, in the same order `ar::Archive::next_entry` returns them.
+/// Returns `None` if the archive doesn't have a symbol table or the symbol table can't be parsed.
+pub(crate) fn symbol_table(data: &[u8]) -> Option<HashMap<String, usize>> {
+    if !data.starts_with(GLOBAL_HEADER) {
+        return None;
+    }
+
+    let mut table = None;
+    // header offset -> member index
+    let mut members = HashMap::new();
+    let mut offset = GLOBAL_HEADER.len();
+    while offset + HEADER_LEN <= data.len() {
+        let header = &data[offset..offset + HEADER_LEN];
+        let size = str::from_utf8(&header[48..58])
+            .ok()?
+            .trim()
+            .parse::<usize>()
+            .ok()?;
+        let start = offset + HEADER_LEN;
+        let end = start.checked_add(size).filter(|end| *end <= data.len())?;
+        let mut contents = &data[start..end];
+
+        let identifier = str::from_utf8(&header[..16]).ok()?.trim_end();
+        let identifier = match identifier.strip_prefix("#1/") {
+            // BSD long names are stored at the start of the member data
+            Some(len) => {
+                let len = len
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|len| *len <= contents.len())?;
+                let (name, rest) = contents.split_at(len);
+                contents = rest;
+                str::from_utf8(name).ok()?.trim_end_matches('\0')
+            }
+            None => identifier,
+        };
+        match identifier {
+            "/" => table = Some(Table::Gnu32(contents)),
+            "/SYM64/" => table = Some(Table::Gnu64(contents)),
+            "__.SYMDEF" | "__.SYMDEF SORTED" => table = Some(Table::Bsd(contents)),
+            // GNU long name table
+            "//" => {}
+            _ => {
+                let index = members.len();
+                members.insert(offset as u64, index);
+            }
+        }
+
+        offset = end + size % 2;
+    }
+
+    let symbols = match table? {
+        Table::Gnu32(data) => parse_gnu(data, 4)?,
+        Table::Gnu64(data) => parse_gnu(data, 8)?,
+        Table::Bsd(data) => parse_bsd(data)?,
+    };
+
+    Some(
+        symbols
+            .into_iter()
+            .filter_map(|(name, offset)| members.get(&offset).map(|index| (name, *index)))
+            .collect(),
+    )
+}
+
+enum Table<'a> {
+    Gnu32(&'a [u8]),
+    Gnu64(&'a [u8]),
+    Bsd(&'a [u8]),
+}
+
+fn read_be(data: &[u8], offset: usize, width: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(width)?)?;
+    Some(bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn read_le32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> Option<(String, usize)> {
+    let rest = data.get(offset..)?;
+    let len = rest.iter().position(|b| *b == 0)?;
+    let name = str::from_utf8(&rest[..len]).ok()?.to_owned();
+    Some((name, offset + len + 1))
+}
+
+// count: N (big endian), offsets: [N] (big endian), names: NUL terminated strings
+fn parse_gnu(data: &[u8], width: usize) -> Option<Vec<(String, u64)>> {
+    let count = read_be(data, 0, width)? as usize;
+    let names_start = count.checked_add(1)?.checked_mul(width)?;
+    let mut name_offset = names_start;
+    let mut symbols = Vec::with_capacity(count.min(data.len() / width));
+    for i in 0..count {
+        let member = read_be(data, (i + 1) * width, width)?;
+        let (name, next) = read_cstr(data, name_offset)?;
+        symbols.push((name, member));
+        name_offset = next;
+    }
+
+    Some(symbols)
+}
+
+// ranlib size: u32, ranlibs: [(name offset: u32, member offset: u32)], strtab size: u32, strtab
+fn parse_bsd(data: &[u8]) -> Option<Vec<(String, u64)>> {
+    let ranlib_size = read_le32(data, 0)?;
+    let strtab_start = ranlib_size.checked_add(8)?;
+    let strtab = data.get(strtab_start..)?;
+    let mut symbols = Vec::with_capacity(ranlib_size / 8);
+    for i in 0..ranlib_size / 8 {
+        let name_offset = read_le32(data, 4 + i * 8)?;
+        let member = read_le32(data, 8 + i * 8)?;
+        let (name, _) = read_cstr(strtab, name_offset)?;
+        symbols.push((name, member as u64));
+    }
+
+    Some(symbols)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn member(identifier: &str, data: &[u8]) -> Vec<u8> {
+        let mut member = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            identifier,
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        )
+        .into_bytes();
+        member.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            member.push(b'\n');
+        }
+        member
+    }
+
+    #[test]
+    fn test_gnu_symbol_table() {
+        let first = member("first.o/", b"abc");
+        let second = member("second.o/", b"defg");
+        // the symbol table is the first member, compute the offsets of the other members
+        let symbols: &[(&str, usize)] = &[("foo", 0), ("bar", 1), ("baz", 1)];
+        let mut names = Vec::new();
+        for (name, _) in symbols {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        let table_size = 4 + 4 * symbols.len() + names.len();
+        let first_offset = GLOBAL_HEADER.len() + HEADER_LEN + table_size + table_size % 2;
+        let offsets = [first_offset, first_offset + first.len()];
+        let mut table = (symbols.len() as u32).to_be_bytes().to_vec();
+        for (_, member) in symbols {
+            table.extend_from_slice(&(offsets[*member] as u32).to_be_bytes());
+        }
+        table.extend_from_slice(&names);
+
+        let mut archive = GLOBAL_HEADER.to_vec();
+        archive.extend(member("/", &table));
+        archive.extend(first);
+        archive.extend(second);
+
+        let table = symbol_table(&archive).unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table["foo"], 0);
+        assert_eq!(table["bar"], 1);
+        assert_eq!(table["baz"], 1);
+    }
+
+    #[test]
+    fn test_no_symbol_table() {
+        let mut archive = GLOBAL_HEADER.to_vec();
+        archive.extend(member("first.o/", b"abc"));
+        assert!(symbol_table(&archive).is_none());
+        assert!(symbol_table(b"not an archive").is_none());
+    }
+}
@@ -3,19 +3,23 @@
 #[cfg(feature = "llvm-proxy")]
 extern crate aya_rustc_llvm_proxy;
 
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use log::*;
 use simplelog::{Config, LevelFilter, SimpleLogger, TermLogger, TerminalMode, WriteLogger};
 use std::{
     collections::HashSet,
     env,
     fs::{self, File},
+    io, mem,
     path::PathBuf,
     str::FromStr,
 };
 use thiserror::Error;
 
-use bpf_linker::{Cpu, Linker, LinkerOptions, OptLevel, OutputType};
+use bpf_linker::{
+    Cpu, DebugInfo, InputSource, KernelProfile, Linker, LinkerInput, LinkerOptions, OptLevel,
+    OutputType, ReportWriter,
+};
 
 #[derive(Debug, Error)]
 enum CliError {
@@ -23,6 +27,17 @@ enum CliError {
     InvalidOptimization(String),
     #[error("unknown emission type: `{0}` - expected one of: `llvm-bc`, `asm`, `llvm-ir`, `obj`")]
     InvalidOutputType(String),
+    #[error(
+        "unknown debug info level: `{0}` - expected one of: `none`, `maps`, `signatures`, \
+         `lines`, `line-tables-only`, `full`"
+    )]
+    InvalidDebugInfo(String),
+    #[error("unknown response file quoting: `{0}` - expected one of: `posix`, `windows`")]
+    InvalidRspQuoting(String),
+    #[error("failed to read response file {0}: {1}")]
+    ResponseFile(PathBuf, io::Error),
+    #[error("response file {0} includes itself")]
+    RecursiveResponseFile(PathBuf),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -62,15 +77,63 @@ impl FromStr for CliOutputType {
         }))
     }
 }
+
+#[derive(Copy, Clone, Debug)]
+struct CliDebugInfo(DebugInfo);
+
+impl FromStr for CliDebugInfo {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use DebugInfo::*;
+        Ok(CliDebugInfo(match s {
+            "none" => None,
+            "maps" => Maps,
+            "signatures" => Signatures,
+            "lines" => Lines,
+            "line-tables-only" => LineTablesOnly,
+            "full" => Full,
+            _ => return Err(CliError::InvalidDebugInfo(s.to_string())),
+        }))
+    }
+}
+
+/// Quoting rules used to split response files into arguments.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RspQuoting {
+    Posix,
+    Windows,
+}
+
+impl FromStr for RspQuoting {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "posix" => RspQuoting::Posix,
+            "windows" => RspQuoting::Windows,
+            _ => return Err(CliError::InvalidRspQuoting(s.to_string())),
+        })
+    }
+}
+
 #[derive(Debug, Parser)]
 struct CommandLine {
     /// LLVM target triple. When not provided, the target is inferred from the inputs
     #[clap(long)]
     target: Option<String>,
 
-    /// Target BPF processor. Can be one of `generic`, `probe`, `v1`, `v2`, `v3`
-    #[clap(long, default_value = "generic")]
-    cpu: Cpu,
+    /// Target BPF processor. Can be one of `generic`, `probe`, `v1`, `v2`, `v3`. Defaults to the
+    /// processor of `--kernel-version`, or `generic`
+    #[clap(long)]
+    cpu: Option<Cpu>,
+
+    /// The oldest kernel the output must load on, like `4.14`, `5.4` or `5.10`. Picks the
+    /// processor, unrolls loops and inlines functions when the kernel needs it, sets the
+    /// `--max-insns` default and fails if the output uses calls, loops, global data or atomics
+    /// the kernel doesn't support. Explicit flags override the profile
+    #[clap(long, value_name = "version")]
+    kernel_version: Option<KernelProfile>,
 
     /// Enable or disable CPU features. The available features are: alu32, dummy, dwarfris. Use
     /// +feature to enable a feature, or -feature to disable it.  For example
@@ -90,14 +153,66 @@ struct CommandLine {
     #[clap(short = 'L', number_of_values = 1)]
     libs: Vec<PathBuf>,
 
+    /// Link the library `name`. Searches the `-L` directories in order for `libname.a`,
+    /// `libname.rlib` or `name.bc`. Use `-l :file` to search for an exact file name
+    #[clap(
+        short = 'l',
+        long = "lib",
+        value_name = "name",
+        use_value_delimiter = true,
+        action = clap::ArgAction::Append
+    )]
+    libraries: Vec<String>,
+
+    /// Link all the members of the archives that follow, instead of only the members that define
+    /// undefined symbols
+    #[clap(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Append
+    )]
+    whole_archive: Vec<bool>,
+
+    /// Only link the members of the archives that follow that define undefined symbols. This is
+    /// the default
+    #[clap(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Append
+    )]
+    no_whole_archive: Vec<bool>,
+
     /// Optimization level. 0-3, s, or z
     #[clap(short = 'O', default_value = "2")]
     optimize: Vec<CliOptLevel>,
 
-    /// Export the symbols specified in the file `path`. The symbols must be separated by new lines
+    /// Export the symbols specified in the file `path`. The symbols must be separated by new lines.
+    /// Lines can be `re:` regular expressions or globs like `--export`, empty lines and lines
+    /// starting with `#` are ignored
     #[clap(long, value_name = "path")]
     export_symbols: Option<PathBuf>,
 
+    /// Export the functions and globals in BPF program, map and license sections, like `xdp`,
+    /// `kprobe/*`, `.maps` and `license`, even if they're not listed with `--export`. Extra
+    /// sections, globs or `re:` regular expressions can be given as a comma separated list
+    #[clap(
+        long,
+        value_name = "sections",
+        num_args = 0..,
+        require_equals = true,
+        use_value_delimiter = true
+    )]
+    export_sections: Option<Vec<String>>,
+
+    /// Export the symbols listed under `global:` in the GNU ld version script `path`. Symbols
+    /// matching a name or glob under `local:` aren't exported even if `--export` globs match them
+    #[clap(long, value_name = "path")]
+    version_script: Option<PathBuf>,
+
     /// Output logs to the given `path`
     #[clap(long, value_name = "path")]
     log_file: Option<PathBuf>,
@@ -132,49 +247,97 @@ struct CommandLine {
     #[clap(long)]
     disable_memory_builtins: bool,
 
+    /// Emit BTF. Keeps debug info through optimization, so that the BPF backend generates
+    /// `.BTF` and `.BTF.ext` sections
+    #[clap(long)]
+    btf: bool,
+
+    /// Debug info to keep for BTF. Can be one of `none`, `maps` (globals in `.maps`),
+    /// `signatures` (maps and exported function signatures), `lines` (maps, and signatures and
+    /// line info of all functions), `line-tables-only` (line info only, without maps or types),
+    /// `full`.
+    /// Defaults to `full` with `--btf`, `none` otherwise
+    #[clap(long, value_name = "level")]
+    debuginfo: Option<CliDebugInfo>,
+
     /// Input files. Can be object files or static libraries
     inputs: Vec<PathBuf>,
 
     // The options below are for wasm-ld compatibility
-    /// Comma separated list of symbols to export. See also `--export-symbols`
+    /// Comma separated list of symbols to export. Symbols can be globs like `xdp_*` or regular
+    /// expressions prefixed with `re:`. See also `--export-symbols`
     #[clap(long, value_name = "symbols", use_value_delimiter = true, action = clap::ArgAction::Append)]
     export: Vec<String>,
 
-    #[clap(
-        short = 'l',
-        long = "lib",
-        use_value_delimiter = true,
-        action = clap::ArgAction::Append,
-        hide = true
-    )]
-    _lib: Option<String>,
-    #[clap(long = "debug", hide = true)]
-    _debug: bool,
-    #[clap(long = "rsp-quoting", hide = true)]
-    _rsp_quoting: Option<String>,
+    /// Keep debug info, same as `--btf`
+    #[clap(long)]
+    debug: bool,
+    /// Quoting style of `@file` response files. Can be one of `posix`, `windows`. Defaults to
+    /// the host's style
+    #[clap(long = "rsp-quoting", value_name = "style")]
+    _rsp_quoting: Option<RspQuoting>,
     #[clap(long = "flavor", hide = true)]
     _flavor: Option<String>,
     #[clap(long = "no-entry", hide = true)]
     _no_entry: bool,
-    #[clap(long = "gc-sections", hide = true)]
-    _gc_sections: bool,
-    #[clap(long = "strip-debug", hide = true)]
-    _strip_debug: bool,
-    #[clap(long = "strip-all", hide = true)]
-    _strip_all: bool,
+    /// Remove the sections of the output object nothing exported refers to
+    #[clap(long)]
+    gc_sections: bool,
+    /// Remove the DWARF sections and the BTF line info from the output object
+    #[clap(long)]
+    strip_debug: bool,
+    /// Like `--strip-debug`, and also remove the local symbols loaders don't need
+    #[clap(long)]
+    strip_all: bool,
+    /// Write a map of the output object to `path`, listing the sections and, for every symbol,
+    /// its section, size, linkage and the input it came from. Also accepted as `-Map`
+    #[clap(long = "Map", value_name = "path")]
+    map: Option<PathBuf>,
+    /// Print the map of the output object to stdout
+    #[clap(long)]
+    print_map: bool,
+    /// Fail if a program's deepest chain of BPF to BPF calls uses more than `bytes` of stack,
+    /// counting each frame rounded up to 32 bytes like the verifier. 0 disables the check. Use
+    /// `--log-level=info` to see the stack usage of every program
+    #[clap(long, value_name = "bytes", default_value = "512")]
+    stack_size_limit: u64,
+    /// Warn if a program and the functions it calls have more than `count` instructions. 0
+    /// disables the check. Defaults to the limit of `--kernel-version`, or 4096. Use
+    /// `--log-level=info` to see the instructions, branches and loops of every program
+    #[clap(long, value_name = "count")]
+    max_insns: Option<u64>,
+    /// Print the functions, globals and aliases removed because nothing exported uses them, and
+    /// the sections removed by `--gc-sections`
+    #[clap(long)]
+    print_gc_sections: bool,
+    /// Print every input that defines or references `symbol`, including archive members that
+    /// aren't linked. Can be a mangled or demangled name
+    #[clap(long, value_name = "symbol", action = clap::ArgAction::Append)]
+    trace_symbol: Vec<String>,
+    /// Print the chain of references from an exported symbol that keeps `symbol` in the output.
+    /// Can be a mangled or demangled name
+    #[clap(long, value_name = "symbol", action = clap::ArgAction::Append)]
+    why_live: Vec<String>,
 }
 
 fn main() {
-    let args = env::args().map(|arg| {
+    let args = match expand_response_files(env::args().collect()) {
+        Ok(args) => args,
+        Err(e) => error(&e.to_string(), clap::error::ErrorKind::Io),
+    };
+    let args = args.into_iter().map(|arg| {
         if arg == "-flavor" {
             "--flavor".to_string()
+        } else if arg == "-Map" || arg.starts_with("-Map=") {
+            format!("-{arg}")
         } else {
             arg
         }
     });
-    let cli = CommandLine::parse_from(args);
+    let matches = CommandLine::command().get_matches_from(args);
+    let cli = CommandLine::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
-    if cli.inputs.is_empty() {
+    if cli.inputs.is_empty() && cli.libraries.is_empty() {
         error("no input files", clap::error::ErrorKind::TooFewValues);
     }
 
@@ -212,29 +375,41 @@ fn main() {
     let CommandLine {
         target,
         cpu,
+        kernel_version,
         cpu_features,
-        inputs,
         output,
         emit,
         libs,
         optimize,
         export_symbols,
+        export_sections,
+        version_script,
         unroll_loops,
         ignore_inline_never,
         dump_module,
         llvm_args,
         disable_expand_memcpy_in_order,
         disable_memory_builtins,
+        btf,
+        debuginfo,
+        debug,
         mut export,
+        gc_sections,
+        strip_debug,
+        strip_all,
+        map,
+        print_map,
+        stack_size_limit,
+        max_insns,
+        print_gc_sections,
+        trace_symbol,
+        why_live,
         ..
     } = cli;
 
     let mut export_symbols = export_symbols
         .map(|path| match fs::read_to_string(path) {
-            Ok(symbols) => symbols
-                .lines()
-                .map(|s| s.to_string())
-                .collect::<HashSet<_>>(),
+            Ok(symbols) => parse_export_symbols(&symbols),
             Err(e) => {
                 error(&e.to_string(), clap::error::ErrorKind::Io);
             }
@@ -246,18 +421,38 @@ fn main() {
         target,
         cpu,
         cpu_features,
-        inputs,
+        inputs: linker_inputs(&matches),
         output,
         output_type: emit.0,
         libs,
         optimize: optimize.last().unwrap().0,
         export_symbols,
+        export_sections: export_sections.map(|sections| sections.into_iter().collect()),
+        version_script,
         unroll_loops,
         ignore_inline_never,
         dump_module,
         llvm_args,
         disable_expand_memcpy_in_order,
         disable_memory_builtins,
+        debug_info: match debuginfo {
+            Some(level) => level.0,
+            None if btf || debug => DebugInfo::Full,
+            None => DebugInfo::None,
+        },
+        strip_debug,
+        strip_all,
+        gc_sections,
+        map_file: map,
+        print_map,
+        stack_size_limit: (stack_size_limit != 0).then_some(stack_size_limit),
+        max_insns,
+        kernel: kernel_version,
+        print_gc_sections,
+        trace_symbols: trace_symbol,
+        why_live,
+        diagnostic_handler: None,
+        report_writer: Some(ReportWriter(Box::new(io::stdout()))),
     };
 
     if let Err(e) = Linker::new(options).link() {
@@ -265,6 +460,231 @@ fn main() {
     }
 }
 
+// Returns the input files and libraries in command line order, applying `--whole-archive` and
+// `--no-whole-archive` to the inputs that follow them.
+fn linker_inputs(matches: &ArgMatches) -> Vec<LinkerInput> {
+    enum Arg {
+        Input(InputSource),
+        WholeArchive(bool),
+    }
+
+    let mut args = Vec::new();
+    if let (Some(indices), Some(values)) = (
+        matches.indices_of("inputs"),
+        matches.get_many::<PathBuf>("inputs"),
+    ) {
+        args.extend(indices.zip(values.map(|path| Arg::Input(InputSource::Path(path.clone())))));
+    }
+    if let (Some(indices), Some(values)) = (
+        matches.indices_of("libraries"),
+        matches.get_many::<String>("libraries"),
+    ) {
+        args.extend(indices.zip(values.map(|name| Arg::Input(InputSource::Library(name.clone())))));
+    }
+    for (id, negate) in [("whole_archive", false), ("no_whole_archive", true)] {
+        if let (Some(indices), Some(values)) =
+            (matches.indices_of(id), matches.get_many::<bool>(id))
+        {
+            args.extend(indices.zip(values.map(|value| Arg::WholeArchive(*value != negate))));
+        }
+    }
+    args.sort_by_key(|(index, _)| *index);
+
+    let mut whole_archive = false;
+    let mut inputs = Vec::new();
+    for (_, arg) in args {
+        match arg {
+            Arg::Input(source) => inputs.push(LinkerInput {
+                source,
+                whole_archive,
+            }),
+            Arg::WholeArchive(value) => whole_archive = value,
+        }
+    }
+
+    inputs
+}
+
+// Returns the symbols listed in an `--export-symbols` file, one per line, skipping empty lines and
+// `#` comments.
+fn parse_export_symbols(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+// Replaces `@path` arguments with the arguments read from the response file at `path`. Response
+// files can themselves contain `@path` arguments.
+fn expand_response_files(args: Vec<String>) -> Result<Vec<String>, CliError> {
+    let quoting = rsp_quoting(&args)?;
+    let mut args = args.into_iter();
+    let mut expanded = args.next().into_iter().collect();
+    expand_args(args, quoting, &mut Vec::new(), &mut expanded)?;
+    Ok(expanded)
+}
+
+// `--rsp-quoting` must be given on the command line since it's needed to read response files
+fn rsp_quoting(args: &[String]) -> Result<RspQuoting, CliError> {
+    let mut quoting = if cfg!(windows) {
+        RspQuoting::Windows
+    } else {
+        RspQuoting::Posix
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--rsp-quoting") {
+            Some("") => match args.next() {
+                Some(value) => value.as_str(),
+                None => break,
+            },
+            Some(value) => match value.strip_prefix('=') {
+                Some(value) => value,
+                None => continue,
+            },
+            None => continue,
+        };
+        quoting = value.parse()?;
+    }
+
+    Ok(quoting)
+}
+
+fn expand_args(
+    args: impl IntoIterator<Item = String>,
+    quoting: RspQuoting,
+    // canonical paths of the response files being expanded, used to detect cycles
+    stack: &mut Vec<PathBuf>,
+    expanded: &mut Vec<String>,
+) -> Result<(), CliError> {
+    for arg in args {
+        let path = match arg.strip_prefix('@') {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => {
+                expanded.push(arg);
+                continue;
+            }
+        };
+        let canonical =
+            fs::canonicalize(&path).map_err(|e| CliError::ResponseFile(path.clone(), e))?;
+        if stack.contains(&canonical) {
+            return Err(CliError::RecursiveResponseFile(path));
+        }
+        let contents =
+            fs::read_to_string(&path).map_err(|e| CliError::ResponseFile(path.clone(), e))?;
+        let args = match quoting {
+            RspQuoting::Posix => tokenize_posix(&contents),
+            RspQuoting::Windows => tokenize_windows(&contents),
+        };
+
+        stack.push(canonical);
+        expand_args(args, quoting, stack, expanded)?;
+        stack.pop();
+    }
+
+    Ok(())
+}
+
+// Splits `s` like a POSIX shell would, without expansions: arguments are separated by whitespace,
+// backslash escapes the next character, single quotes preserve everything up to the closing quote
+// and double quotes preserve everything but backslash escapes.
+fn tokenize_posix(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                arg.extend(chars.next());
+            }
+            '\'' | '"' => {
+                in_arg = true;
+                while let Some(next) = chars.next() {
+                    match next {
+                        next if next == c => break,
+                        '\\' if c == '"' => arg.extend(chars.next()),
+                        next => arg.push(next),
+                    }
+                }
+            }
+            c => {
+                in_arg = true;
+                arg.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    args
+}
+
+// Splits `s` following the rules of `CommandLineToArgvW`: arguments are separated by whitespace,
+// double quotes group whitespace, `""` inside quotes is a literal quote and backslashes are only
+// special when they precede a double quote: 2n backslashes produce n backslashes and a quote
+// delimiter, 2n + 1 backslashes produce n backslashes and a literal quote.
+fn tokenize_windows(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                let mut count = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    count += 1;
+                }
+                if chars.peek() == Some(&'"') {
+                    arg.push_str(&"\\".repeat(count / 2));
+                    if count % 2 == 1 {
+                        arg.push('"');
+                        chars.next();
+                    }
+                } else {
+                    arg.push_str(&"\\".repeat(count));
+                }
+            }
+            '"' => {
+                in_arg = true;
+                if quoted && chars.next_if_eq(&'"').is_some() {
+                    arg.push('"');
+                } else {
+                    quoted = !quoted;
+                }
+            }
+            c => {
+                in_arg = true;
+                arg.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+
+    args
+}
+
 fn error(desc: &str, kind: clap::error::ErrorKind) -> ! {
     clap::Error::raw(kind, desc.to_string()).exit();
 }
@@ -341,4 +761,201 @@ mod test {
             vec![PathBuf::from("symbols.o"), PathBuf::from("rcgu.o")]
         );
     }
+
+    #[test]
+    fn test_libraries() {
+        let args = vec![
+            "bpf-linker",
+            "-L",
+            "target/debug/deps",
+            "-l",
+            "foo",
+            "--lib=bar,baz",
+            "symbols.o",
+            "-o",
+            "/tmp/bin.o",
+        ];
+        let cli = CommandLine::parse_from(args);
+        assert_eq!(cli.libraries, vec!["foo", "bar", "baz"]);
+        assert_eq!(cli.inputs, vec![PathBuf::from("symbols.o")]);
+    }
+
+    #[test]
+    fn test_whole_archive() {
+        let args = vec![
+            "bpf-linker",
+            "first.o",
+            "--whole-archive",
+            "libfoo.a",
+            "-l",
+            "bar",
+            "--no-whole-archive",
+            "libbaz.rlib",
+            "--whole-archive",
+            "-lqux,quux",
+            "-o",
+            "/tmp/bin.o",
+        ];
+        let matches = CommandLine::command().get_matches_from(args);
+        let inputs = linker_inputs(&matches)
+            .into_iter()
+            .map(|input| (input.source, input.whole_archive))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            vec![
+                (InputSource::Path(PathBuf::from("first.o")), false),
+                (InputSource::Path(PathBuf::from("libfoo.a")), true),
+                (InputSource::Library("bar".to_string()), true),
+                (InputSource::Path(PathBuf::from("libbaz.rlib")), false),
+                (InputSource::Library("qux".to_string()), true),
+                (InputSource::Library("quux".to_string()), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_posix() {
+        assert_eq!(
+            tokenize_posix("a  b\n\t'c d' \"e \\\"f\\\\\" g\\ h '\\' ''"),
+            vec!["a", "b", "c d", "e \"f\\", "g h", "\\", ""]
+        );
+        assert!(tokenize_posix(" \n ").is_empty());
+    }
+
+    #[test]
+    fn test_tokenize_windows() {
+        assert_eq!(
+            tokenize_windows(r#"a  b "c d" C:\dir\ e\\"f g" h\"i "j""k" """#),
+            vec!["a", "b", "c d", r"C:\dir\", r"e\f g", r#"h"i"#, r#"j"k"#, ""]
+        );
+    }
+
+    #[test]
+    fn test_expand_response_files() {
+        let dir = env::temp_dir().join(format!("bpf-linker-rsp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let outer = dir.join("outer.rsp");
+        let inner = dir.join("inner.rsp");
+        fs::write(&outer, format!("-o out.o '@{}' last.o", inner.display())).unwrap();
+        fs::write(&inner, "'with space.o' -L dir").unwrap();
+
+        let args = vec![
+            "bpf-linker".to_string(),
+            "--rsp-quoting=posix".to_string(),
+            format!("@{}", outer.display()),
+            "@".to_string(),
+        ];
+        assert_eq!(
+            expand_response_files(args).unwrap(),
+            vec![
+                "bpf-linker",
+                "--rsp-quoting=posix",
+                "-o",
+                "out.o",
+                "with space.o",
+                "-L",
+                "dir",
+                "last.o",
+                "@"
+            ]
+        );
+
+        fs::write(&inner, format!("@{}", outer.display())).unwrap();
+        let args = vec!["bpf-linker".to_string(), format!("@{}", outer.display())];
+        assert!(matches!(
+            expand_response_files(args),
+            Err(CliError::RecursiveResponseFile(_))
+        ));
+
+        let args = vec![
+            "bpf-linker".to_string(),
+            format!("@{}", dir.join("missing.rsp").display()),
+        ];
+        assert!(matches!(
+            expand_response_files(args),
+            Err(CliError::ResponseFile(..))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rsp_quoting() {
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            rsp_quoting(&args(&["bpf-linker", "--rsp-quoting", "windows"])).unwrap(),
+            RspQuoting::Windows
+        );
+        assert_eq!(
+            rsp_quoting(&args(&["bpf-linker", "--rsp-quoting=posix"])).unwrap(),
+            RspQuoting::Posix
+        );
+        assert!(rsp_quoting(&args(&["bpf-linker", "--rsp-quoting=bash"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_export_symbols() {
+        let symbols = parse_export_symbols(
+            "# programs\nxdp_*\n\n  re:^tc_(ingress|egress)$\n\t# maps\nEVENTS\n",
+        );
+        let mut symbols = symbols.into_iter().collect::<Vec<_>>();
+        symbols.sort();
+        assert_eq!(symbols, ["EVENTS", "re:^tc_(ingress|egress)$", "xdp_*"]);
+    }
+
+    #[test]
+    fn test_kernel_version() {
+        let cli = CommandLine::parse_from(["bpf-linker", "-o", "/tmp/bin.o", "symbols.o"]);
+        assert!(cli.kernel_version.is_none());
+        assert!(cli.cpu.is_none());
+
+        let cli = CommandLine::parse_from([
+            "bpf-linker",
+            "--kernel-version",
+            "5.8",
+            "-o",
+            "/tmp/bin.o",
+            "symbols.o",
+        ]);
+        assert_eq!(cli.kernel_version.unwrap().version, (5, 4));
+
+        assert!(CommandLine::try_parse_from([
+            "bpf-linker",
+            "--kernel-version=4.9",
+            "-o",
+            "/tmp/bin.o",
+            "symbols.o",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_export_sections() {
+        let cli = CommandLine::parse_from(["bpf-linker", "-o", "/tmp/bin.o", "symbols.o"]);
+        assert_eq!(cli.export_sections, None);
+
+        // without `=` the next argument is an input, not a section
+        let cli = CommandLine::parse_from([
+            "bpf-linker",
+            "--export-sections",
+            "symbols.o",
+            "-o",
+            "/tmp/bin.o",
+        ]);
+        assert_eq!(cli.export_sections, Some(vec![]));
+        assert_eq!(cli.inputs, vec![PathBuf::from("symbols.o")]);
+
+        let cli = CommandLine::parse_from([
+            "bpf-linker",
+            "--export-sections=my_section,re:^prog/",
+            "symbols.o",
+            "-o",
+            "/tmp/bin.o",
+        ]);
+        assert_eq!(
+            cli.export_sections,
+            Some(vec!["my_section".to_string(), "re:^prog/".to_string()])
+        );
+    }
 }
@@ -0,0 +1,821 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::DebugInfo;
+
+// tags of the derived types BTF doesn't allow names on
+const UNNAMED_TAGS: &[&str] = &[
+    "DW_TAG_pointer_type",
+    "DW_TAG_const_type",
+    "DW_TAG_volatile_type",
+    "DW_TAG_restrict_type",
+    "DW_TAG_atomic_type",
+];
+
+/// Rewrites the debug info in the textual IR `ir` into a form LLVM's BTF generation and the
+/// kernel accept:
+///
+/// - enums with data variants, described by a struct containing a `DW_TAG_variant_part`, become
+///   unions of their variants and of the variant part's discriminator, named `discriminant` if
+///   it has no name
+/// - zero sized basic types, like `()`, become empty structs
+/// - type, function and global variable names become valid C identifiers, `Option<u32>` becomes
+///   `Option_u32_`, with a `_<n>` suffix when different names would become the same
+/// - pointer, const and volatile types lose their names
+/// - function types without a return type, which rustc emits with
+///   `-C debuginfo=line-tables-only`, return void
+/// - unless `level` is `DebugInfo::Maps`, functions without debug info, like the ones from crates
+///   built without it or the ones `DebugInfo::Signatures` doesn't keep, get a `void()`
+///   subprogram. Once BTF has func info for a function, the kernel requires it for every
+///   function of the program.
+///
+/// At `DebugInfo::LineTablesOnly`, functions are described as `void()` and the compile units only
+/// keep line tables, so that BTF line info can be generated whatever the types are.
+///
+/// The C API can't modify debug info nodes, which is why this works on the textual IR, where
+/// metadata is printed one node per line as `!<id> = [distinct ]!<Kind>(<field>: <value>, ...)`.
+pub(crate) fn rewrite_debug_info(ir: &str, level: DebugInfo) -> String {
+    let line_tables_only = level == DebugInfo::LineTablesOnly;
+    let mut lines = ir.lines().map(str::to_owned).collect::<Vec<_>>();
+
+    // metadata id -> line
+    let mut definitions = HashMap::new();
+    // BTreeMap so that new tuples always get the same ids
+    let mut nodes = BTreeMap::new();
+    let mut tuples = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if let Some((id, definition)) = metadata_definition(line) {
+            definitions.insert(id, index);
+            if let Some(node) = Node::parse(definition) {
+                nodes.insert(id, node);
+            } else if let Some(items) = parse_tuple(definition) {
+                tuples.insert(id, items);
+            }
+        }
+    }
+    let mut next_id = definitions.keys().max().map_or(0, |id| id + 1);
+    let mut changed = HashSet::new();
+
+    // enums with data variants
+    let mut new_nodes = Vec::new();
+    let mut unions = Vec::new();
+    let mut unnamed_discriminators = Vec::new();
+    for (id, node) in &nodes {
+        if let Some((mut members, variants, discriminator)) = enum_variants(node, &nodes, &tuples) {
+            // keep the discriminator of the variant part. Union members must be at offset 0, so
+            // if it isn't, it's wrapped in an anonymous struct that puts it at its offset.
+            if let Some((discriminator, member)) = discriminator {
+                if member.get("name").is_none() {
+                    unnamed_discriminators.push(discriminator);
+                }
+                match member.get("offset") {
+                    None | Some("0") => members.push(format!("!{discriminator}")),
+                    Some(offset) => {
+                        let (elements, structure, wrapper) = (next_id, next_id + 1, next_id + 2);
+                        next_id += 3;
+                        let size = offset
+                            .parse::<u64>()
+                            .ok()
+                            .zip(member.get("size").and_then(|size| size.parse::<u64>().ok()))
+                            .map(|(offset, size)| format!(", size: {}", offset + size))
+                            .unwrap_or_default();
+                        new_nodes.push(format!("!{elements} = !{{!{discriminator}}}"));
+                        new_nodes.push(format!(
+                            "!{structure} = !DICompositeType(tag: DW_TAG_structure_type, \
+                             scope: !{id}{size}, elements: !{elements})"
+                        ));
+                        new_nodes.push(format!(
+                            "!{wrapper} = !DIDerivedType(tag: DW_TAG_member, scope: !{id}, \
+                             baseType: !{structure}{size})"
+                        ));
+                        members.push(format!("!{wrapper}"));
+                    }
+                }
+            }
+
+            let elements = if members.is_empty() {
+                variants.to_owned()
+            } else {
+                // keep the other members next to the variants
+                let mut items = members;
+                items.extend(
+                    parse_ref(variants)
+                        .and_then(|id| tuples.get(&id))
+                        .into_iter()
+                        .flatten()
+                        .cloned(),
+                );
+                let tuple = next_id;
+                next_id += 1;
+                new_nodes.push(format!("!{tuple} = !{{{}}}", items.join(", ")));
+                format!("!{tuple}")
+            };
+            unions.push((*id, elements));
+        }
+    }
+    for (id, elements) in unions {
+        let node = nodes.get_mut(&id).unwrap();
+        node.set("tag", "DW_TAG_union_type".to_owned());
+        node.set("elements", elements);
+        changed.insert(id);
+    }
+    for id in unnamed_discriminators {
+        let node = nodes.get_mut(&id).unwrap();
+        node.set("name", "\"discriminant\"".to_owned());
+        changed.insert(id);
+    }
+
+    // names that are already valid identifiers, the sanitized names mustn't collide with them
+    let mut names = Names::new(
+        nodes
+            .values()
+            .filter_map(|node| node.get("name"))
+            .filter(|name| sanitize_name(name) == *name),
+    );
+
+    // `!{null}` and `!DISubroutineType(types: !{null})`, allocated when first used
+    let void_types = next_id;
+    let void_function = next_id + 1;
+    let mut void_types_used = false;
+    let mut void_function_used = false;
+
+    for (id, node) in nodes.iter_mut() {
+        // function types without a return type
+        if node.kind == "DISubroutineType" {
+            let empty = match node.get("types") {
+                None | Some("null") | Some("!{}") => true,
+                Some(types) => matches!(
+                    parse_ref(types).and_then(|id| tuples.get(&id)),
+                    Some(items) if items.is_empty()
+                ),
+            };
+            if empty {
+                node.set("types", format!("!{void_types}"));
+                void_types_used = true;
+                changed.insert(*id);
+            }
+        }
+
+        if line_tables_only {
+            match node.kind.as_str() {
+                "DICompileUnit" => {
+                    node.set("emissionKind", "LineTablesOnly".to_owned());
+                    for field in ["enums", "retainedTypes", "globals", "imports"] {
+                        node.remove(field);
+                    }
+                    changed.insert(*id);
+                }
+                "DISubprogram" => {
+                    node.set("type", format!("!{void_function}"));
+                    for field in [
+                        "declaration",
+                        "retainedNodes",
+                        "templateParams",
+                        "thrownTypes",
+                    ] {
+                        node.remove(field);
+                    }
+                    // methods are scoped in their type
+                    if let Some(file) = node.get("file") {
+                        let file = file.to_owned();
+                        node.set("scope", file);
+                    }
+                    void_function_used = true;
+                    changed.insert(*id);
+                }
+                _ => {}
+            }
+        }
+
+        // zero sized basic types
+        if node.kind == "DIBasicType" && matches!(node.get("size"), None | Some("0")) {
+            let mut fields = vec![("tag".to_owned(), "DW_TAG_structure_type".to_owned())];
+            if let Some(name) = node.get("name") {
+                fields.push(("name".to_owned(), name.to_owned()));
+            }
+            fields.push(("elements".to_owned(), "!{}".to_owned()));
+            *node = Node {
+                distinct: node.distinct,
+                kind: "DICompositeType".to_owned(),
+                fields,
+            };
+            changed.insert(*id);
+        }
+
+        // names
+        match node.kind.as_str() {
+            "DIDerivedType" if matches!(node.get("tag"), Some(t) if UNNAMED_TAGS.contains(&t)) => {
+                let removed = node.remove("name");
+                if removed {
+                    changed.insert(*id);
+                }
+            }
+            "DIBasicType" | "DIDerivedType" | "DICompositeType" | "DISubprogram"
+            | "DIGlobalVariable" => {
+                if let Some(name) = node.get("name") {
+                    let sanitized = names.sanitize(name);
+                    if sanitized != name {
+                        node.set("name", sanitized);
+                        changed.insert(*id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // functions without debug info
+    let mut subprograms = Vec::new();
+    let unit = nodes
+        .iter()
+        .find(|(_, node)| node.kind == "DICompileUnit" && level != DebugInfo::Maps)
+        .map(|(id, node)| (*id, node.get("file").unwrap_or("null")));
+    if let Some((unit, file)) = unit {
+        let mut next_id = void_function + 1;
+        let mut location = None;
+        for line in lines.iter_mut() {
+            if line.starts_with("define ") {
+                location = None;
+                let name = function_name(line);
+                match (line.strip_suffix(" {"), name) {
+                    (Some(definition), Some(name)) if !definition.contains(" !dbg !") => {
+                        let (subprogram, loc) = (next_id, next_id + 1);
+                        next_id += 2;
+                        subprograms.push(format!(
+                            "!{subprogram} = distinct !DISubprogram(name: {}, scope: {file}, \
+                             file: {file}, type: !{void_function}, spFlags: DISPFlagDefinition, \
+                             unit: !{unit})",
+                            names.sanitize(&format!("\"{name}\""))
+                        ));
+                        // the verifier requires calls in functions with debug info to have a
+                        // location
+                        subprograms.push(format!(
+                            "!{loc} = !DILocation(line: 0, scope: !{subprogram})"
+                        ));
+                        *line = format!("{definition} !dbg !{subprogram} {{");
+                        location = Some(loc);
+                        void_function_used = true;
+                    }
+                    _ => {}
+                }
+            } else if line == "}" {
+                location = None;
+            } else if let Some(loc) = location {
+                if is_call(line) && !line.contains("!dbg") {
+                    line.push_str(&format!(", !dbg !{loc}"));
+                }
+            }
+        }
+    }
+
+    for id in changed {
+        lines[definitions[&id]] = format!("!{id} = {}", nodes[&id]);
+    }
+    if void_types_used || void_function_used {
+        new_nodes.push(format!("!{void_types} = !{{null}}"));
+    }
+    if void_function_used {
+        new_nodes.push(format!(
+            "!{void_function} = !DISubroutineType(types: !{void_types})"
+        ));
+    }
+    lines.extend(new_nodes);
+    lines.extend(subprograms);
+
+    let mut ir = lines.join("\n");
+    ir.push('\n');
+    ir
+}
+
+// returns the name of the function defined by the `define` line `line`
+fn function_name(line: &str) -> Option<&str> {
+    let name = &line[line.find(" @")? + 2..];
+    match name.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').map(|(name, _)| name),
+        None => name.split_once('(').map(|(name, _)| name),
+    }
+}
+
+// returns whether the instruction `line` is a call
+fn is_call(line: &str) -> bool {
+    let instruction = line.trim_start();
+    let instruction = match instruction.split_once(" = ") {
+        Some((value, instruction)) if value.starts_with('%') => instruction,
+        _ => instruction,
+    };
+    let instruction = ["tail ", "musttail ", "notail "]
+        .iter()
+        .find_map(|marker| instruction.strip_prefix(marker))
+        .unwrap_or(instruction);
+    instruction.starts_with("call ")
+}
+
+// the members of an enum other than the variant part, the reference to the variant part's
+// elements, which are the variants, and the variant part's discriminator with its id
+type EnumVariants<'a> = (Vec<String>, &'a str, Option<(u32, &'a Node)>);
+
+// if `node` is an enum with data variants, returns its members and variants
+fn enum_variants<'a>(
+    node: &'a Node,
+    nodes: &'a BTreeMap<u32, Node>,
+    tuples: &'a HashMap<u32, Vec<String>>,
+) -> Option<EnumVariants<'a>> {
+    if node.kind != "DICompositeType" || node.get("tag") != Some("DW_TAG_structure_type") {
+        return None;
+    }
+    let items = tuples.get(&parse_ref(node.get("elements")?)?)?;
+
+    let mut members = Vec::new();
+    let mut variants = None;
+    let mut discriminator = None;
+    for item in items {
+        match parse_ref(item).and_then(|id| nodes.get(&id)) {
+            Some(n) if n.get("tag") == Some("DW_TAG_variant_part") && variants.is_none() => {
+                variants = Some(n.get("elements")?);
+                discriminator = n
+                    .get("discriminator")
+                    .and_then(parse_ref)
+                    .and_then(|id| Some((id, nodes.get(&id)?)));
+            }
+            _ => members.push(item.clone()),
+        }
+    }
+
+    Some((members, variants?, discriminator))
+}
+
+// splits `!<id> = <definition>`
+fn metadata_definition(line: &str) -> Option<(u32, &str)> {
+    let (id, definition) = line.strip_prefix('!')?.split_once(" = ")?;
+    Some((id.parse().ok()?, definition))
+}
+
+// parses a `!<id>` reference
+fn parse_ref(s: &str) -> Option<u32> {
+    s.strip_prefix('!')?.parse().ok()
+}
+
+// parses `!{<item>, ...}`
+fn parse_tuple(s: &str) -> Option<Vec<String>> {
+    let items = s.strip_prefix("!{")?.strip_suffix('}')?;
+    Some(
+        split_top_level(items)
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+// splits `s` on the commas that aren't inside strings, parentheses or braces
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '{' if !in_string => depth += 1,
+            ')' | '}' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                items.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() {
+        items.push(last);
+    }
+
+    items
+}
+
+// turns the quoted string `name` into a valid C identifier, replacing invalid characters with `_`
+fn sanitize_name(name: &str) -> String {
+    let unquoted = match name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+    {
+        Some(unquoted) => unquoted,
+        None => return name.to_owned(),
+    };
+
+    let mut bytes = Vec::with_capacity(unquoted.len());
+    let mut rest = unquoted.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        // non printable characters are escaped as \XX
+        match (
+            b,
+            tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()),
+        ) {
+            (b'\\', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+
+    let mut sanitized = bytes
+        .into_iter()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b == b'_' {
+                b as char
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+
+    format!("\"{sanitized}\"")
+}
+
+// sanitizes names so that different names don't end up the same: when a sanitized name is
+// taken by another name, a `_<n>` suffix is added
+struct Names {
+    // sanitized name -> the name it was sanitized from
+    taken: HashMap<String, String>,
+}
+
+impl Names {
+    fn new<'a>(valid: impl Iterator<Item = &'a str>) -> Self {
+        Names {
+            taken: valid
+                .map(|name| (name.to_owned(), name.to_owned()))
+                .collect(),
+        }
+    }
+
+    fn sanitize(&mut self, name: &str) -> String {
+        let sanitized = sanitize_name(name);
+        let mut candidate = sanitized.clone();
+        for suffix in 1.. {
+            match self.taken.get(&candidate) {
+                Some(original) if original != name => {
+                    candidate = match sanitized.strip_suffix('"') {
+                        Some(unquoted) => format!("{unquoted}_{suffix}\""),
+                        None => format!("{sanitized}_{suffix}"),
+                    };
+                }
+                Some(_) => break,
+                None => {
+                    self.taken.insert(candidate.clone(), name.to_owned());
+                    break;
+                }
+            }
+        }
+
+        candidate
+    }
+}
+
+/// A specialized metadata node, like `!DICompositeType(tag: DW_TAG_structure_type, ...)`.
+struct Node {
+    distinct: bool,
+    kind: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Node {
+    fn parse(s: &str) -> Option<Node> {
+        let (distinct, s) = match s.strip_prefix("distinct ") {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (kind, fields) = s.strip_prefix('!')?.split_once('(')?;
+        if !kind.starts_with("DI") {
+            return None;
+        }
+        let fields = split_top_level(fields.strip_suffix(')')?)
+            .into_iter()
+            .map(|field| {
+                let (name, value) = field.split_once(": ")?;
+                Some((name.to_owned(), value.to_owned()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Node {
+            distinct,
+            kind: kind.to_owned(),
+            fields,
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        match self.fields.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((name.to_owned(), value)),
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        let len = self.fields.len();
+        self.fields.retain(|(n, _)| n != name);
+        self.fields.len() != len
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.distinct {
+            write!(f, "distinct ")?;
+        }
+        write!(f, "!{}(", self.kind)?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}: {value}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RUSTC_DEBUG_INFO: &str = include_str!("../tests/inputs/rustc-debug-info.ll");
+
+    // returns the definition of the node `id` in the rewritten IR
+    fn definition(ir: &str, id: u32) -> String {
+        ir.lines()
+            .find_map(|line| match metadata_definition(line) {
+                Some((i, definition)) if i == id => Some(definition.to_owned()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    // returns the id and the definition of the first node of `ir` containing `pattern`
+    fn find(ir: &str, pattern: &str) -> (u32, String) {
+        ir.lines()
+            .filter_map(metadata_definition)
+            .find(|(_, definition)| definition.contains(pattern))
+            .map(|(id, definition)| (id, definition.to_owned()))
+            .unwrap()
+    }
+
+    // returns the definition of the node called `name` in `ir`
+    fn named(ir: &str, name: &str) -> String {
+        find(ir, &format!("name: \"{name}\"")).1
+    }
+
+    // returns the definitions of the elements of the composite type `node`
+    fn elements(ir: &str, node: &str) -> Vec<String> {
+        let node = Node::parse(node).unwrap();
+        let elements = definition(ir, parse_ref(node.get("elements").unwrap()).unwrap());
+        parse_tuple(&elements)
+            .unwrap()
+            .iter()
+            .map(|element| definition(ir, parse_ref(element).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_enum_with_data_variants() {
+        let ir = rewrite_debug_info(RUSTC_DEBUG_INFO, DebugInfo::Full);
+        // Option<u32> becomes a union of its variant structs
+        let option = named(&ir, "Option_u32_");
+        assert!(option.starts_with(
+            "!DICompositeType(tag: DW_TAG_union_type, name: \"Option_u32_\", scope: "
+        ));
+        assert!(option.contains(" size: 64, align: 32, flags: DIFlagPublic, elements: "));
+        // the discriminator is kept, and named since rustc doesn't name it
+        let members = elements(&ir, &option);
+        assert_eq!(members.len(), 3);
+        assert!(members[0].contains("name: \"discriminant\""));
+        assert!(members[0].contains("flags: DIFlagArtificial"));
+        assert!(members[1].contains("name: \"None\""));
+        assert!(members[2].contains("name: \"Some\""));
+        // so does aya_types::Action, nested in Result<Action, ()>
+        assert!(named(&ir, "Action")
+            .starts_with("!DICompositeType(tag: DW_TAG_union_type, name: \"Action\""));
+        let members = elements(&ir, &named(&ir, "Result_aya_types__Action_____"));
+        assert_eq!(members.len(), 3);
+        assert!(members[0].contains("flags: DIFlagArtificial"));
+        assert!(members[1].contains("name: \"Ok\""));
+        assert!(members[2].contains("name: \"Err\""));
+    }
+
+    #[test]
+    fn test_discriminator_offset() {
+        let ir = "!1 = !DICompositeType(tag: DW_TAG_structure_type, name: \"E\", elements: !2)\n\
+                  !2 = !{!3}\n\
+                  !3 = !DICompositeType(tag: DW_TAG_variant_part, scope: !1, elements: !4, \
+                  discriminator: !5)\n\
+                  !4 = !{!6}\n\
+                  !5 = !DIDerivedType(tag: DW_TAG_member, name: \"tag\", scope: !1, baseType: null, \
+                  size: 8, offset: 64)\n\
+                  !6 = !DIDerivedType(tag: DW_TAG_member, name: \"V\", scope: !3, baseType: null)\n";
+        let ir = rewrite_debug_info(ir, DebugInfo::Full);
+        // union members are at offset 0, the discriminator is wrapped in a struct putting it at
+        // its offset
+        assert_eq!(
+            definition(&ir, 1),
+            "!DICompositeType(tag: DW_TAG_union_type, name: \"E\", elements: !10)"
+        );
+        assert_eq!(definition(&ir, 10), "!{!9, !6}");
+        assert_eq!(
+            definition(&ir, 9),
+            "!DIDerivedType(tag: DW_TAG_member, scope: !1, baseType: !8, size: 72)"
+        );
+        assert_eq!(
+            definition(&ir, 8),
+            "!DICompositeType(tag: DW_TAG_structure_type, scope: !1, size: 72, elements: !7)"
+        );
+        assert_eq!(definition(&ir, 7), "!{!5}");
+        // named discriminators keep their name
+        assert!(definition(&ir, 5).contains("name: \"tag\""));
+    }
+
+    #[test]
+    fn test_enum_with_other_members() {
+        let ir = "!1 = !DICompositeType(tag: DW_TAG_structure_type, name: \"E\", elements: !2)\n\
+                  !2 = !{!3, !4}\n\
+                  !3 = !DIDerivedType(tag: DW_TAG_member, name: \"a\", baseType: null)\n\
+                  !4 = !DICompositeType(tag: DW_TAG_variant_part, scope: !1, elements: !5)\n\
+                  !5 = !{!6}\n\
+                  !6 = !DIDerivedType(tag: DW_TAG_member, name: \"V\", scope: !4, baseType: null)\n";
+        let ir = rewrite_debug_info(ir, DebugInfo::Full);
+        assert_eq!(
+            definition(&ir, 1),
+            "!DICompositeType(tag: DW_TAG_union_type, name: \"E\", elements: !7)"
+        );
+        assert_eq!(definition(&ir, 7), "!{!3, !6}");
+    }
+
+    #[test]
+    fn test_zero_sized_types() {
+        let ir = rewrite_debug_info(RUSTC_DEBUG_INFO, DebugInfo::Full);
+        // ()
+        assert_eq!(
+            named(&ir, "__"),
+            "!DICompositeType(tag: DW_TAG_structure_type, name: \"__\", elements: !{})"
+        );
+        // PhantomData<u32> is already an empty struct, only its name changes
+        assert_eq!(
+            named(&ir, "PhantomData_u32_"),
+            named(RUSTC_DEBUG_INFO, "PhantomData<u32>").replace("<u32>", "_u32_")
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        let ir = rewrite_debug_info(RUSTC_DEBUG_INFO, DebugInfo::Full);
+        assert!(named(&ir, "HashMap_u32__u64_").starts_with("!DICompositeType("));
+        assert!(named(&ir, "Result_aya_types__Action_____").starts_with("!DICompositeType("));
+        // linkage names are left alone
+        assert!(
+            named(&ir, "is_null_u8_").contains("linkageName: \"_ZN4core3ptr7mut_ptr31_$LT$impl")
+        );
+        // valid names are left alone
+        assert_eq!(
+            named(&ir, "u32"),
+            "!DIBasicType(name: \"u32\", size: 32, encoding: DW_ATE_unsigned)"
+        );
+        assert!(named(&ir, "EVENTS").starts_with("distinct !DIGlobalVariable("));
+        // pointers don't have names
+        let (context, _) = find(&ir, "name: \"XdpContext\"");
+        find(
+            &ir,
+            &format!(
+                "!DIDerivedType(tag: DW_TAG_pointer_type, baseType: !{context}, size: 64, \
+                 align: 64, dwarfAddressSpace: 0)"
+            ),
+        );
+        assert!(!ir.contains("name: \"&aya_types::XdpContext\""));
+        // namespaces aren't emitted in BTF
+        assert!(named(&ir, "{impl#0}").starts_with("!DINamespace(name: \"{impl#0}\", scope: "));
+
+        assert_eq!(sanitize_name("\"1st\\22\""), "\"_1st_\"");
+    }
+
+    #[test]
+    fn test_name_collisions() {
+        let ir = "!1 = !DIBasicType(name: \"Option_u32_\", size: 32)\n\
+                  !2 = !DIBasicType(name: \"Option<u32>\", size: 32)\n\
+                  !3 = !DIBasicType(name: \"Option(u32)\", size: 32)\n\
+                  !4 = !DIBasicType(name: \"Option<u32>\", size: 32)\n";
+        let ir = rewrite_debug_info(ir, DebugInfo::Full);
+        // the name that is already valid is kept, the others get a suffix, the same for the
+        // same name
+        assert!(definition(&ir, 1).contains("name: \"Option_u32_\""));
+        assert!(definition(&ir, 2).contains("name: \"Option_u32__1\""));
+        assert!(definition(&ir, 3).contains("name: \"Option_u32__2\""));
+        assert!(definition(&ir, 4).contains("name: \"Option_u32__1\""));
+    }
+
+    #[test]
+    fn test_empty_function_types() {
+        let ir = "!1 = !DISubroutineType(types: !2)\n\
+                  !2 = !{}\n\
+                  !3 = !DISubroutineType(types: !4)\n\
+                  !4 = !{null}\n";
+        let ir = rewrite_debug_info(ir, DebugInfo::Full);
+        assert_eq!(definition(&ir, 1), "!DISubroutineType(types: !5)");
+        assert_eq!(definition(&ir, 5), "!{null}");
+        assert_eq!(definition(&ir, 3), "!DISubroutineType(types: !4)");
+        // the void function type is only added when needed
+        assert_eq!(ir.lines().count(), 5);
+    }
+
+    #[test]
+    fn test_line_tables_only() {
+        let ir = rewrite_debug_info(RUSTC_DEBUG_INFO, DebugInfo::LineTablesOnly);
+        let (_, unit) = find(&ir, "!DICompileUnit(");
+        assert!(unit.contains("emissionKind: LineTablesOnly"));
+        assert!(!unit.contains("globals:"));
+
+        // the void function type is added at the end
+        let mut new_nodes = ir.lines().rev().filter_map(metadata_definition);
+        let (void_function, function_type) = new_nodes.next().unwrap();
+        let (void_types, types) = new_nodes.next().unwrap();
+        assert_eq!(types, "!{null}");
+        assert_eq!(
+            function_type,
+            format!("!DISubroutineType(types: !{void_types})")
+        );
+        // functions are void(), scoped in their file
+        let (file, _) = find(&ir, "filename: \"library/core/src/ptr/mut_ptr.rs\"");
+        let (_, function) = find(&ir, "linkageName: \"_ZN4core3ptr7mut_ptr31_$LT$impl");
+        assert!(function.contains(&format!(
+            "scope: !{file}, file: !{file}, line: 22, type: !{void_function},"
+        )));
+        assert!(!function.contains("templateParams:"));
+        assert!(!function.contains("retainedNodes:"));
+        assert!(named(&ir, "classify").contains(&format!("type: !{void_function},")));
+    }
+
+    #[test]
+    fn test_functions_without_debug_info() {
+        let ir = "define i32 @f() !dbg !3 {\n\
+                  \x20 ret i32 0, !dbg !4\n\
+                  }\n\
+                  define internal i32 @\"g<u8>\"(ptr %0) #0 section \"xdp\" {\n\
+                  \x20 %2 = tail call i32 @f()\n\
+                  \x20 call void @llvm.dbg.value(metadata ptr %0)\n\
+                  \x20 ret i32 %2\n\
+                  }\n\
+                  !1 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !2)\n\
+                  !2 = !DIFile(filename: \"main.rs\", directory: \"/\")\n\
+                  !3 = distinct !DISubprogram(name: \"f\", unit: !1)\n\
+                  !4 = !DILocation(line: 1, scope: !3)\n";
+        let ir = rewrite_debug_info(ir, DebugInfo::Full);
+        let lines = ir.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "define i32 @f() !dbg !3 {");
+        assert_eq!(
+            lines[3],
+            "define internal i32 @\"g<u8>\"(ptr %0) #0 section \"xdp\" !dbg !7 {"
+        );
+        assert_eq!(lines[4], "  %2 = tail call i32 @f(), !dbg !8");
+        assert_eq!(
+            lines[5],
+            "  call void @llvm.dbg.value(metadata ptr %0), !dbg !8"
+        );
+        assert_eq!(lines[6], "  ret i32 %2");
+        assert_eq!(
+            definition(&ir, 7),
+            "distinct !DISubprogram(name: \"g_u8_\", scope: !2, file: !2, type: !6, spFlags: \
+             DISPFlagDefinition, unit: !1)"
+        );
+        assert_eq!(definition(&ir, 6), "!DISubroutineType(types: !5)");
+        assert_eq!(definition(&ir, 8), "!DILocation(line: 0, scope: !7)");
+
+        // functions don't get debug info when only maps keep theirs
+        let ir = rewrite_debug_info(
+            &ir.replace(" !dbg !7", "").replace(", !dbg !8", ""),
+            DebugInfo::Maps,
+        );
+        assert!(!ir.contains("define internal i32 @\"g<u8>\"(ptr %0) #0 section \"xdp\" !dbg"));
+        assert!(!ir.contains("call i32 @f(), !dbg"));
+    }
+
+    #[test]
+    fn test_unchanged_lines() {
+        let ir = rewrite_debug_info(RUSTC_DEBUG_INFO, DebugInfo::Full);
+        // everything else is kept as it is
+        for (before, after) in RUSTC_DEBUG_INFO.lines().zip(ir.lines()) {
+            if !before.contains("DI") {
+                assert_eq!(before, after);
+            }
+        }
+        // the unions of the three enums get new elements, including their discriminators
+        assert_eq!(RUSTC_DEBUG_INFO.lines().count() + 3, ir.lines().count());
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::llvm::Call;
+
+/// A call the verifier rejects.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Rejected {
+    /// A cycle of calls, starting and ending with the same function.
+    Recursion(Vec<String>),
+    /// A call through a function pointer, with the chain of calls from a root to the function
+    /// making it and where the call is.
+    IndirectCall(Vec<String>, String),
+}
+
+/// Checks the functions `roots` can call, directly or not, for recursion and calls through
+/// function pointers. `calls` has the calls of every defined function, the others are ignored.
+pub(crate) fn check_calls(
+    roots: &HashSet<String>,
+    calls: &HashMap<String, Vec<Call>>,
+) -> Result<(), Rejected> {
+    // sort the roots so that the same error is reported on every run
+    let mut roots = roots
+        .iter()
+        .filter(|root| calls.contains_key(*root))
+        .collect::<Vec<_>>();
+    roots.sort();
+
+    let mut checked = HashSet::new();
+    let mut chain = Vec::new();
+    for root in roots {
+        check(root, calls, &mut checked, &mut chain)?;
+    }
+
+    Ok(())
+}
+
+// depth first, `chain` has the calls that led to `function`
+fn check<'a>(
+    function: &'a str,
+    calls: &'a HashMap<String, Vec<Call>>,
+    checked: &mut HashSet<&'a str>,
+    chain: &mut Vec<&'a str>,
+) -> Result<(), Rejected> {
+    if let Some(start) = chain.iter().position(|caller| *caller == function) {
+        let mut cycle = chain[start..]
+            .iter()
+            .map(|caller| caller.to_string())
+            .collect::<Vec<_>>();
+        cycle.push(function.to_string());
+        return Err(Rejected::Recursion(cycle));
+    }
+    if checked.contains(function) {
+        return Ok(());
+    }
+    let function_calls = match calls.get(function) {
+        Some(function_calls) => function_calls,
+        None => return Ok(()),
+    };
+
+    chain.push(function);
+    for call in function_calls {
+        match call {
+            Call::Direct(callee) => check(callee, calls, checked, chain)?,
+            Call::Indirect(site) => {
+                return Err(Rejected::IndirectCall(
+                    chain.iter().map(|caller| caller.to_string()).collect(),
+                    site.clone(),
+                ))
+            }
+        }
+    }
+    chain.pop();
+    checked.insert(function);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(functions: &[(&str, &[&str])]) -> HashMap<String, Vec<Call>> {
+        functions
+            .iter()
+            .map(|(function, calls)| {
+                (
+                    function.to_string(),
+                    calls
+                        .iter()
+                        .map(|call| match call.strip_prefix('*') {
+                            Some(site) => Call::Indirect(site.to_string()),
+                            None => Call::Direct(call.to_string()),
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn roots(roots: &[&str]) -> HashSet<String> {
+        roots.iter().map(|root| root.to_string()).collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_no_recursion() {
+        // `leaf` is called twice, `helper` isn't defined, `MAP` isn't a function
+        let calls = graph(&[
+            ("prog", &["lookup", "leaf", "helper"]),
+            ("lookup", &["leaf"]),
+            ("leaf", &[]),
+        ]);
+        assert_eq!(check_calls(&roots(&["prog", "MAP"]), &calls), Ok(()));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let calls = graph(&[
+            ("prog", &["walk"]),
+            ("walk", &["leaf", "visit"]),
+            ("visit", &["walk"]),
+            ("leaf", &[]),
+        ]);
+        assert_eq!(
+            check_calls(&roots(&["prog"]), &calls),
+            Err(Rejected::Recursion(names(&["walk", "visit", "walk"])))
+        );
+
+        let calls = graph(&[("prog", &["prog"])]);
+        assert_eq!(
+            check_calls(&roots(&["prog"]), &calls),
+            Err(Rejected::Recursion(names(&["prog", "prog"])))
+        );
+
+        // only what the roots can call is checked
+        let calls = graph(&[("prog", &[]), ("unused", &["unused"])]);
+        assert_eq!(check_calls(&roots(&["prog"]), &calls), Ok(()));
+    }
+
+    #[test]
+    fn test_indirect_call() {
+        let calls = graph(&[("prog", &["dispatch"]), ("dispatch", &["*prog.c:10:5"])]);
+        assert_eq!(
+            check_calls(&roots(&["prog"]), &calls),
+            Err(Rejected::IndirectCall(
+                names(&["prog", "dispatch"]),
+                "prog.c:10:5".to_string()
+            ))
+        );
+    }
+}
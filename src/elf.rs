@@ -0,0 +1,755 @@
+use std::{collections::HashMap, io::Read, str};
+
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+use ruzstd::StreamingDecoder;
+
+/// `sh_flags` bit set on sections that start with a compression header.
+const SHF_COMPRESSED: u64 = 0x800;
+/// `ch_type` of zlib compressed sections.
+const ELFCOMPRESS_ZLIB: u32 = 1;
+/// `ch_type` of zstd compressed sections.
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+/// `sh_type` values.
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHT_GROUP: u32 = 17;
+const SHT_SYMTAB_SHNDX: u32 = 18;
+const SHT_LLVM_ADDRSIG: u32 = 0x6fff_4c03;
+/// `sh_flags` bit set on sections that occupy memory when the program runs.
+pub(crate) const SHF_ALLOC: u64 = 0x2;
+/// `sh_flags` bit set on sections containing instructions.
+pub(crate) const SHF_EXECINSTR: u64 = 0x4;
+/// `sh_flags` bit set on sections whose `sh_info` is a section index.
+const SHF_INFO_LINK: u64 = 0x40;
+/// Section indices from this one up are reserved, they don't refer to sections.
+const SHN_LORESERVE: u16 = 0xff00;
+/// `e_machine` of BPF objects.
+pub(crate) const EM_BPF: u16 = 247;
+/// Symbol bindings and types, in `st_info`.
+pub(crate) const STB_LOCAL: u8 = 0;
+pub(crate) const STT_FUNC: u8 = 2;
+pub(crate) const STT_SECTION: u8 = 3;
+pub(crate) const STT_FILE: u8 = 4;
+
+/// Error decompressing an ELF section.
+#[derive(Debug)]
+pub(crate) enum DecompressError {
+    /// The section uses a compression type we don't know about.
+    UnsupportedCompression(u32),
+    /// The compressed data is invalid.
+    InvalidData(String),
+}
+
+/// Minimal ELF reader, only parses what's needed to find out if a section is compressed and to
+/// decompress it.
+pub(crate) struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    pub(crate) fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 6 || &data[..4] != b"\x7FELF" {
+            return None;
+        }
+
+        let is_64 = match data[4] {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+        let little_endian = match data[5] {
+            1 => true,
+            2 => false,
+            _ => return None,
+        };
+
+        Some(Elf {
+            data,
+            is_64,
+            little_endian,
+        })
+    }
+
+    fn read(&self, offset: usize, width: usize) -> Option<u64> {
+        let bytes = self.data.get(offset..offset.checked_add(width)?)?;
+        let fold = |acc: u64, b: &u8| (acc << 8) | *b as u64;
+        Some(if self.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    }
+
+    // reads an address sized field, at `offset_32` in ELF32 and `offset_64` in ELF64
+    fn read_word(&self, offset_32: usize, offset_64: usize) -> Option<u64> {
+        if self.is_64 {
+            self.read(offset_64, 8)
+        } else {
+            self.read(offset_32, 4)
+        }
+    }
+
+    /// Returns the `sh_flags` of the section called `name`.
+    fn section_flags(&self, name: &str) -> Option<u64> {
+        let (shoff, shentsize, shnum, shstrndx) = if self.is_64 {
+            (
+                self.read(0x28, 8)?,
+                self.read(0x3A, 2)?,
+                self.read(0x3C, 2)?,
+                self.read(0x3E, 2)?,
+            )
+        } else {
+            (
+                self.read(0x20, 4)?,
+                self.read(0x2E, 2)?,
+                self.read(0x30, 2)?,
+                self.read(0x32, 2)?,
+            )
+        };
+        // the offsets come from the file, malformed ones must not overflow
+        let header = |index: u64| -> Option<usize> {
+            let offset = shoff.checked_add(index.checked_mul(shentsize)?)?;
+            usize::try_from(offset).ok()
+        };
+
+        let strtab_header = header(shstrndx)?;
+        let strtab = self.read_word(
+            strtab_header.checked_add(16)?,
+            strtab_header.checked_add(24)?,
+        )?;
+        let strtab = usize::try_from(strtab).ok()?;
+        for index in 0..shnum {
+            let header = header(index)?;
+            let name_offset = strtab.checked_add(self.read(header, 4)? as usize)?;
+            let section_name = self.data.get(name_offset..)?;
+            let len = section_name.iter().position(|b| *b == 0)?;
+            if str::from_utf8(&section_name[..len]).ok()? == name {
+                let flags = header.checked_add(8)?;
+                return self.read_word(flags, flags);
+            }
+        }
+
+        None
+    }
+
+    /// Returns whether the section called `name` is compressed.
+    pub(crate) fn is_compressed(&self, name: &str) -> bool {
+        self.section_flags(name)
+            .map(|flags| flags & SHF_COMPRESSED != 0)
+            .unwrap_or(false)
+    }
+
+    /// Decompresses the `contents` of a compressed section, which start with a compression
+    /// header.
+    pub(crate) fn decompress(&self, contents: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        let invalid = || DecompressError::InvalidData("truncated compression header".to_string());
+        // Elf32_Chdr is {ch_type, ch_size, ch_addralign}, Elf64_Chdr has an extra ch_reserved
+        // field after ch_type and 64 bit size and alignment
+        let elf = Elf {
+            data: contents,
+            ..*self
+        };
+        let ch_type = elf.read(0, 4).ok_or_else(invalid)? as u32;
+        let (size, header_len) = if self.is_64 {
+            (elf.read(8, 8).ok_or_else(invalid)?, 24)
+        } else {
+            (elf.read(4, 4).ok_or_else(invalid)?, 12)
+        };
+        let compressed = contents.get(header_len..).ok_or_else(invalid)?;
+
+        // ch_size comes from the input, so it's only used as a limit: decompression stops once
+        // the output is larger than it, and nothing is allocated up front
+        let data = match ch_type {
+            ELFCOMPRESS_ZLIB => {
+                let limit = usize::try_from(size).unwrap_or(usize::MAX);
+                decompress_to_vec_zlib_with_limit(compressed, limit)
+                    .map_err(|e| DecompressError::InvalidData(format!("zlib: {e:?}")))?
+            }
+            ELFCOMPRESS_ZSTD => {
+                let mut reader = compressed;
+                let decoder = StreamingDecoder::new(&mut reader)
+                    .map_err(|e| DecompressError::InvalidData(format!("zstd: {e}")))?;
+                let mut data = Vec::new();
+                decoder
+                    .take(size.saturating_add(1))
+                    .read_to_end(&mut data)
+                    .map_err(|e| DecompressError::InvalidData(format!("zstd: {e}")))?;
+                data
+            }
+            ty => return Err(DecompressError::UnsupportedCompression(ty)),
+        };
+
+        if data.len() as u64 != size {
+            return Err(DecompressError::InvalidData(format!(
+                "expected {size} bytes, decompressed {}",
+                data.len()
+            )));
+        }
+
+        Ok(data)
+    }
+}
+
+/// A section of an [`Object`].
+pub(crate) struct Section {
+    pub(crate) name: String,
+    pub(crate) sh_type: u32,
+    pub(crate) flags: u64,
+    addr: u64,
+    link: u32,
+    info: u32,
+    addralign: u64,
+    entsize: u64,
+    pub(crate) size: u64,
+    pub(crate) data: Vec<u8>,
+    /// The entries of `SHT_REL` and `SHT_RELA` sections.
+    pub(crate) relocations: Vec<Relocation>,
+}
+
+impl Section {
+    /// Returns the index of the section the relocations in this section apply to.
+    pub(crate) fn relocated_section(&self) -> Option<usize> {
+        matches!(self.sh_type, SHT_REL | SHT_RELA).then_some(self.info as usize)
+    }
+}
+
+/// A symbol of an [`Object`].
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    info: u8,
+    other: u8,
+    /// The index of the section the symbol is defined in.
+    pub(crate) section: u16,
+    pub(crate) value: u64,
+    pub(crate) size: u64,
+}
+
+impl Symbol {
+    pub(crate) fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    pub(crate) fn kind(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    /// Returns the index of the section the symbol is defined in, if it's defined in one.
+    pub(crate) fn section_index(&self) -> Option<usize> {
+        (self.section != 0 && self.section < SHN_LORESERVE).then_some(self.section as usize)
+    }
+}
+
+/// A relocation of an [`Object`].
+pub(crate) struct Relocation {
+    pub(crate) offset: u64,
+    /// Index of the symbol in [`Object::symbols`].
+    pub(crate) symbol: u32,
+    kind: u32,
+    addend: u64,
+}
+
+/// Minimal ELF64 relocatable object reader and writer, for the objects LLVM emits. Sections,
+/// symbols and relocations can be removed, and the object written back.
+pub(crate) struct Object {
+    header: Vec<u8>,
+    pub(crate) little_endian: bool,
+    /// `e_machine`, the architecture of the object.
+    pub(crate) machine: u16,
+    /// The sections, the first one is the null section.
+    pub(crate) sections: Vec<Section>,
+    /// The symbols of the symbol table, the first one is the null symbol.
+    pub(crate) symbols: Vec<Symbol>,
+    shstrndx: usize,
+}
+
+impl Object {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, String> {
+        let elf = Elf::parse(data).ok_or("not an ELF file")?;
+        if !elf.is_64 {
+            return Err("not an ELF64 file".to_string());
+        }
+        let truncated = || "truncated ELF file".to_string();
+        let read = |offset: usize, width: usize| elf.read(offset, width).ok_or_else(truncated);
+
+        let shoff = read(0x28, 8)? as usize;
+        let shentsize = read(0x3A, 2)? as usize;
+        let shnum = read(0x3C, 2)? as usize;
+        let shstrndx = read(0x3E, 2)? as usize;
+
+        let mut sections = Vec::with_capacity(shnum);
+        let mut names = Vec::with_capacity(shnum);
+        for index in 0..shnum {
+            // the offsets come from the file, malformed ones must not overflow
+            let header = index
+                .checked_mul(shentsize)
+                .and_then(|offset| shoff.checked_add(offset))
+                .ok_or_else(truncated)?;
+            let field = |offset: usize, width: usize| {
+                read(header.checked_add(offset).ok_or_else(truncated)?, width)
+            };
+            let sh_type = field(4, 4)? as u32;
+            let offset = field(24, 8)? as usize;
+            let size = field(32, 8)?;
+            let data = if sh_type == SHT_NOBITS {
+                Vec::new()
+            } else {
+                usize::try_from(size)
+                    .ok()
+                    .and_then(|size| offset.checked_add(size))
+                    .and_then(|end| data.get(offset..end))
+                    .ok_or_else(truncated)?
+                    .to_vec()
+            };
+            if matches!(sh_type, SHT_GROUP | SHT_SYMTAB_SHNDX) {
+                return Err(format!("unsupported section type {sh_type}"));
+            }
+            names.push(field(0, 4)? as usize);
+            sections.push(Section {
+                name: String::new(),
+                sh_type,
+                flags: field(8, 8)?,
+                addr: field(16, 8)?,
+                link: field(40, 4)? as u32,
+                info: field(44, 4)? as u32,
+                addralign: field(48, 8)?,
+                entsize: field(56, 8)?,
+                size,
+                data,
+                relocations: Vec::new(),
+            });
+        }
+        let shstrtab = sections.get(shstrndx).ok_or_else(truncated)?.data.clone();
+        for (section, name) in sections.iter_mut().zip(names) {
+            section.name = string(&shstrtab, name)?;
+        }
+
+        let mut symbols = Vec::new();
+        if let Some(symtab) = sections.iter().find(|s| s.sh_type == SHT_SYMTAB) {
+            let strtab = &sections
+                .get(symtab.link as usize)
+                .ok_or_else(truncated)?
+                .data;
+            let symtab = Elf {
+                data: &symtab.data,
+                ..elf
+            };
+            for entry in (0..symtab.data.len()).step_by(24) {
+                let read = |offset: usize, width: usize| {
+                    symtab.read(entry + offset, width).ok_or_else(truncated)
+                };
+                symbols.push(Symbol {
+                    name: string(strtab, read(0, 4)? as usize)?,
+                    info: read(4, 1)? as u8,
+                    other: read(5, 1)? as u8,
+                    section: read(6, 2)? as u16,
+                    value: read(8, 8)?,
+                    size: read(16, 8)?,
+                });
+            }
+        }
+
+        for section in sections.iter_mut() {
+            let entsize = match section.sh_type {
+                SHT_REL => 16,
+                SHT_RELA => 24,
+                _ => continue,
+            };
+            let relocations = Elf {
+                data: &section.data,
+                ..elf
+            };
+            for entry in (0..section.data.len()).step_by(entsize) {
+                let read = |offset: usize, width: usize| {
+                    relocations
+                        .read(entry + offset, width)
+                        .ok_or_else(truncated)
+                };
+                let info = read(8, 8)?;
+                section.relocations.push(Relocation {
+                    offset: read(0, 8)?,
+                    symbol: (info >> 32) as u32,
+                    kind: info as u32,
+                    addend: if entsize == 24 { read(16, 8)? } else { 0 },
+                });
+            }
+        }
+
+        let mut object = Object {
+            header: data.get(..64).ok_or_else(truncated)?.to_vec(),
+            little_endian: elf.little_endian,
+            machine: read(0x12, 2)? as u16,
+            sections,
+            symbols,
+            shstrndx,
+        };
+        // address significance tables list symbol indices, they're only an optimization hint
+        object.remove_sections(|section| section.sh_type == SHT_LLVM_ADDRSIG);
+
+        Ok(object)
+    }
+
+    /// Removes the sections `remove` returns true for, along with their relocation sections, the
+    /// symbols defined in them and the relocations against those symbols. Returns the names of
+    /// the removed sections, relocation sections excluded.
+    pub(crate) fn remove_sections(&mut self, remove: impl Fn(&Section) -> bool) -> Vec<String> {
+        let symtab = self.symtab_index();
+        let strtab = symtab.map(|index| self.sections[index].link as usize);
+        let mut removed = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| {
+                index != 0
+                    && Some(index) != symtab
+                    && Some(index) != strtab
+                    && index != self.shstrndx
+                    && remove(section)
+            })
+            .collect::<Vec<_>>();
+        let names = removed
+            .iter()
+            .zip(&self.sections)
+            .filter(|(removed, _)| **removed)
+            .map(|(_, section)| section.name.clone())
+            .collect();
+        for (index, section) in self.sections.iter().enumerate() {
+            if let Some(target) = section.relocated_section() {
+                if removed.get(target).copied().unwrap_or(false) {
+                    removed[index] = true;
+                }
+            }
+        }
+
+        self.retain_symbols(
+            |_, symbol| !matches!(symbol.section_index(), Some(index) if removed[index]),
+        );
+
+        let mut new_indices = Vec::with_capacity(removed.len());
+        let mut next = 0;
+        for removed in &removed {
+            new_indices.push(next);
+            if !removed {
+                next += 1;
+            }
+        }
+        let mut index = 0;
+        self.sections.retain(|_| {
+            index += 1;
+            !removed[index - 1]
+        });
+        for section in &mut self.sections {
+            if section.link != 0 {
+                section.link = new_indices[section.link as usize];
+            }
+            if section.relocated_section().is_some() || section.flags & SHF_INFO_LINK != 0 {
+                section.info = new_indices[section.info as usize];
+            }
+        }
+        for symbol in &mut self.symbols {
+            if let Some(index) = symbol.section_index() {
+                symbol.section = new_indices[index] as u16;
+            }
+        }
+        self.shstrndx = new_indices[self.shstrndx] as usize;
+
+        names
+    }
+
+    /// Keeps the symbols `keep` returns true for, called with the index and the symbol, and
+    /// removes the others along with the relocations against them.
+    pub(crate) fn retain_symbols(&mut self, mut keep: impl FnMut(usize, &Symbol) -> bool) {
+        let mut new_indices = Vec::with_capacity(self.symbols.len());
+        let mut next = 0;
+        for (index, symbol) in self.symbols.iter().enumerate() {
+            if index == 0 || keep(index, symbol) {
+                new_indices.push(Some(next));
+                next += 1;
+            } else {
+                new_indices.push(None);
+            }
+        }
+        let mut index = 0;
+        self.symbols.retain(|_| {
+            index += 1;
+            new_indices[index - 1].is_some()
+        });
+        for section in &mut self.sections {
+            section.relocations.retain_mut(|relocation| {
+                match new_indices
+                    .get(relocation.symbol as usize)
+                    .copied()
+                    .flatten()
+                {
+                    Some(index) => {
+                        relocation.symbol = index;
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+    }
+
+    fn symtab_index(&self) -> Option<usize> {
+        self.sections.iter().position(|s| s.sh_type == SHT_SYMTAB)
+    }
+
+    /// Returns the object file.
+    pub(crate) fn write(&self) -> Vec<u8> {
+        let symtab = self.symtab_index();
+        let strtab = symtab.map(|index| self.sections[index].link as usize);
+
+        // string tables are rebuilt, the symbol and section names can share the same one
+        let mut tables = HashMap::new();
+        let mut section_names = Vec::new();
+        for section in &self.sections {
+            let table = tables.entry(self.shstrndx).or_insert_with(StringTable::new);
+            section_names.push(table.add(&section.name));
+        }
+        let mut symbol_names = Vec::new();
+        if let Some(strtab) = strtab {
+            let table = tables.entry(strtab).or_insert_with(StringTable::new);
+            for symbol in &self.symbols {
+                symbol_names.push(table.add(&symbol.name));
+            }
+        }
+
+        let mut out = self.header.clone();
+        let mut headers = Vec::new();
+        for (index, section) in self.sections.iter().enumerate() {
+            let mut info = section.info;
+            let data = if let Some(table) = tables.get(&index) {
+                table.data.clone()
+            } else if Some(index) == symtab {
+                let mut data = Vec::new();
+                for (symbol, name) in self.symbols.iter().zip(&symbol_names) {
+                    self.put(&mut data, *name as u64, 4);
+                    data.extend([symbol.info, symbol.other]);
+                    self.put(&mut data, symbol.section as u64, 2);
+                    self.put(&mut data, symbol.value, 8);
+                    self.put(&mut data, symbol.size, 8);
+                }
+                // sh_info is one greater than the index of the last local symbol
+                info = self
+                    .symbols
+                    .iter()
+                    .position(|symbol| symbol.binding() != STB_LOCAL)
+                    .unwrap_or(self.symbols.len()) as u32;
+                data
+            } else if matches!(section.sh_type, SHT_REL | SHT_RELA) {
+                let mut data = Vec::new();
+                for relocation in &section.relocations {
+                    self.put(&mut data, relocation.offset, 8);
+                    self.put(
+                        &mut data,
+                        (relocation.symbol as u64) << 32 | relocation.kind as u64,
+                        8,
+                    );
+                    if section.sh_type == SHT_RELA {
+                        self.put(&mut data, relocation.addend, 8);
+                    }
+                }
+                data
+            } else {
+                section.data.clone()
+            };
+
+            let offset = if index == 0 {
+                0
+            } else {
+                let align = section.addralign.max(1) as usize;
+                out.resize(out.len().div_ceil(align) * align, 0);
+                out.len()
+            };
+            let size = if section.sh_type == SHT_NOBITS {
+                section.size
+            } else {
+                data.len() as u64
+            };
+            out.extend(data);
+
+            self.put(&mut headers, section_names[index] as u64, 4);
+            self.put(&mut headers, section.sh_type as u64, 4);
+            self.put(&mut headers, section.flags, 8);
+            self.put(&mut headers, section.addr, 8);
+            self.put(&mut headers, offset as u64, 8);
+            self.put(&mut headers, size, 8);
+            self.put(&mut headers, section.link as u64, 4);
+            self.put(&mut headers, info as u64, 4);
+            self.put(&mut headers, section.addralign, 8);
+            self.put(&mut headers, section.entsize, 8);
+        }
+
+        out.resize(out.len().div_ceil(8) * 8, 0);
+        let shoff = out.len() as u64;
+        out.extend(headers);
+
+        let mut header = Vec::new();
+        self.put(&mut header, shoff, 8);
+        out[0x28..0x30].copy_from_slice(&header);
+        header.clear();
+        self.put(&mut header, 64, 2);
+        self.put(&mut header, self.sections.len() as u64, 2);
+        self.put(&mut header, self.shstrndx as u64, 2);
+        out[0x3A..0x40].copy_from_slice(&header);
+
+        out
+    }
+
+    // appends the `width` low bytes of `value` to `out`
+    fn put(&self, out: &mut Vec<u8>, value: u64, width: usize) {
+        if self.little_endian {
+            out.extend(&value.to_le_bytes()[..width]);
+        } else {
+            out.extend(&value.to_be_bytes()[8 - width..]);
+        }
+    }
+}
+
+// reads the NUL terminated string at `offset` in the string table `table`
+fn string(table: &[u8], offset: usize) -> Result<String, String> {
+    let bytes = table
+        .get(offset..)
+        .ok_or_else(|| format!("invalid string table offset {offset}"))?;
+    let len = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| format!("unterminated string at offset {offset}"))?;
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            data: vec![0],
+            offsets: HashMap::from([(String::new(), 0)]),
+        }
+    }
+
+    // returns the offset of `s`, adding it if needed
+    fn add(&mut self, s: &str) -> u32 {
+        if let Some(offset) = self.offsets.get(s) {
+            return *offset;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend(s.as_bytes());
+        self.data.push(0);
+        self.offsets.insert(s.to_owned(), offset);
+        offset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // .llvmbc is section 3 in the fixtures and contains the compressed bitcode of
+    // tests/inputs/fixture.ll
+    fn llvmbc_contents(data: &[u8]) -> &[u8] {
+        let elf = Elf::parse(data).unwrap();
+        let shoff = elf.read(0x28, 8).unwrap() as usize;
+        let shentsize = elf.read(0x3A, 2).unwrap() as usize;
+        let header = shoff + 3 * shentsize;
+        let offset = elf.read(header + 24, 8).unwrap() as usize;
+        let size = elf.read(header + 32, 8).unwrap() as usize;
+        &data[offset..offset + size]
+    }
+
+    #[test]
+    fn test_decompress_zlib() {
+        let data = include_bytes!("../tests/inputs/llvmbc-zlib.o");
+        let elf = Elf::parse(data).unwrap();
+        assert!(elf.is_compressed(".llvmbc"));
+        assert!(!elf.is_compressed(".text"));
+        let bitcode = elf.decompress(llvmbc_contents(data)).unwrap();
+        assert_eq!(&bitcode[..4], b"BC\xC0\xDE");
+    }
+
+    #[test]
+    fn test_malformed_section_headers() {
+        let data = include_bytes!("../tests/inputs/llvmbc-zlib.o");
+        // e_shoff, e_shentsize and e_shstrndx that overflow when computing header offsets
+        for (offset, value) in [
+            (0x28, &[0xFF; 8][..]),
+            (0x3A, &[0xFF; 2]),
+            (0x3E, &[0xFF; 2]),
+        ] {
+            let mut data = data.to_vec();
+            data[offset..offset + value.len()].copy_from_slice(value);
+            let elf = Elf::parse(&data).unwrap();
+            assert!(!elf.is_compressed(".llvmbc"));
+        }
+    }
+
+    #[test]
+    fn test_object_overflowing_offsets() {
+        let data = include_bytes!("../tests/inputs/llvmbc-zlib.o");
+        let shoff = Elf::parse(data).unwrap().read(0x28, 8).unwrap() as usize;
+        // e_shoff, and the sh_offset of the second section, that overflow when computing the
+        // offsets of the section headers and the section contents
+        for (offset, value) in [(0x28, [0xFF; 8]), (shoff + 64 + 24, [0xFF; 8])] {
+            let mut data = data.to_vec();
+            data[offset..offset + value.len()].copy_from_slice(&value);
+            assert_eq!(
+                Object::parse(&data).err().as_deref(),
+                Some("truncated ELF file")
+            );
+        }
+    }
+
+    #[test]
+    fn test_decompress_zstd() {
+        let data = include_bytes!("../tests/inputs/llvmbc-zstd.o");
+        let elf = Elf::parse(data).unwrap();
+        assert!(elf.is_compressed(".llvmbc"));
+        let bitcode = elf.decompress(llvmbc_contents(data)).unwrap();
+        assert_eq!(&bitcode[..4], b"BC\xC0\xDE");
+    }
+
+    #[test]
+    fn test_decompress_wrong_size() {
+        for data in [
+            &include_bytes!("../tests/inputs/llvmbc-zlib.o")[..],
+            include_bytes!("../tests/inputs/llvmbc-zstd.o"),
+        ] {
+            let elf = Elf::parse(data).unwrap();
+            // ch_size is at offset 8 of the compression header, it's neither trusted to allocate
+            // the output nor allowed to be exceeded
+            for size in [u64::MAX, 4] {
+                let mut contents = llvmbc_contents(data).to_vec();
+                contents[8..16].copy_from_slice(&size.to_le_bytes());
+                assert!(matches!(
+                    elf.decompress(&contents),
+                    Err(DecompressError::InvalidData(_))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_unsupported() {
+        let data = include_bytes!("../tests/inputs/llvmbc-unsupported.o");
+        let elf = Elf::parse(data).unwrap();
+        assert!(matches!(
+            elf.decompress(llvmbc_contents(data)),
+            Err(DecompressError::UnsupportedCompression(3))
+        ));
+    }
+
+    #[test]
+    fn test_uncompressed() {
+        let data = include_bytes!("../tests/inputs/fat-lto.o");
+        let elf = Elf::parse(data).unwrap();
+        assert!(!elf.is_compressed(".llvm.lto"));
+    }
+}
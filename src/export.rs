@@ -0,0 +1,309 @@
+use std::{cell::Cell, collections::HashSet};
+
+use regex::Regex;
+
+use crate::LinkerError;
+
+/// Prefix of the export patterns that are regular expressions.
+const REGEX_PREFIX: &str = "re:";
+
+/// The sections libbpf and aya load programs, maps and metadata from, exported with
+/// `--export-sections`.
+const BPF_SECTIONS: &[&str] = &[
+    "kprobe/*",
+    "kretprobe/*",
+    "uprobe/*",
+    "uretprobe/*",
+    "tracepoint/*",
+    "tp/*",
+    "raw_tracepoint/*",
+    "raw_tp/*",
+    "tp_btf/*",
+    "fentry/*",
+    "fexit/*",
+    "lsm/*",
+    "perf_event",
+    "xdp",
+    "xdp/*",
+    "xdp.frags",
+    "tc",
+    "tc/*",
+    "classifier",
+    "classifier/*",
+    "socket",
+    "socket/*",
+    "cgroup/*",
+    "cgroup_skb/*",
+    "sk_msg",
+    "sk_skb/*",
+    "sockops",
+    "maps",
+    "maps/*",
+    ".maps",
+    "license",
+    "version",
+];
+
+/// The symbols to export, built from [`LinkerOptions::export_symbols`](crate::LinkerOptions) and
+/// [`LinkerOptions::export_sections`](crate::LinkerOptions).
+///
+/// Entries starting with `re:` are regular expressions, entries containing `*` or `?` are globs,
+/// anything else is an exact symbol or section name.
+pub(crate) struct ExportSymbols {
+    symbols: Patterns,
+    sections: Option<Patterns>,
+    local: Patterns,
+}
+
+impl ExportSymbols {
+    pub(crate) fn new(
+        symbols: &HashSet<String>,
+        sections: Option<&HashSet<String>>,
+        local: &HashSet<String>,
+    ) -> Result<ExportSymbols, LinkerError> {
+        let sections = match sections {
+            Some(sections) => {
+                let mut patterns = Patterns::new(sections, false)?;
+                patterns.extend(Patterns::new(
+                    &BPF_SECTIONS
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<_>>(),
+                    true,
+                )?);
+                Some(patterns)
+            }
+            None => None,
+        };
+
+        Ok(ExportSymbols {
+            symbols: Patterns::new(symbols, false)?,
+            sections,
+            local: Patterns::new(local, false)?,
+        })
+    }
+
+    /// Returns whether the symbol `name`, defined in `section`, is exported. Every pattern
+    /// matching `name` or `section` is marked as used. Symbols matching a `local` name or pattern
+    /// are only exported when they're also listed by name.
+    pub(crate) fn contains(&self, name: &str, section: Option<&str>) -> bool {
+        if self.local.matches(name) {
+            return self.symbols.names.contains(name);
+        }
+        let by_name = self.symbols.matches(name);
+        let by_section = match (&self.sections, section) {
+            (Some(sections), Some(section)) => sections.matches(section),
+            _ => false,
+        };
+
+        by_name || by_section
+    }
+
+    /// Returns the symbol patterns that haven't matched any symbol passed to [`Self::contains`].
+    pub(crate) fn unmatched_symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.unmatched()
+    }
+
+    /// Returns the section patterns that haven't matched any section passed to
+    /// [`Self::contains`]. The default BPF sections aren't reported.
+    pub(crate) fn unmatched_sections(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().flat_map(Patterns::unmatched)
+    }
+}
+
+struct Patterns {
+    names: HashSet<String>,
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    source: String,
+    regex: Regex,
+    matched: Cell<bool>,
+    builtin: bool,
+}
+
+impl Patterns {
+    fn new<'a>(
+        entries: impl IntoIterator<Item = &'a String>,
+        builtin: bool,
+    ) -> Result<Patterns, LinkerError> {
+        let mut names = HashSet::new();
+        let mut patterns = Vec::new();
+        for entry in entries {
+            if !is_pattern(entry) {
+                names.insert(entry.clone());
+                continue;
+            }
+            let regex = match entry.strip_prefix(REGEX_PREFIX) {
+                Some(regex) => Regex::new(regex),
+                None => Regex::new(&glob_to_regex(entry)),
+            }
+            .map_err(|e| LinkerError::InvalidExportPattern(entry.clone(), e.to_string()))?;
+            patterns.push(Pattern {
+                source: entry.clone(),
+                regex,
+                matched: Cell::new(false),
+                builtin,
+            });
+        }
+        // sort so that warnings about unmatched patterns come out in a stable order
+        patterns.sort_by(|a, b| a.source.cmp(&b.source));
+
+        Ok(Patterns { names, patterns })
+    }
+
+    fn extend(&mut self, other: Patterns) {
+        self.names.extend(other.names);
+        self.patterns.extend(other.patterns);
+    }
+
+    // every pattern is checked so that all the patterns matching `s` are marked as used
+    fn matches(&self, s: &str) -> bool {
+        let mut matches = self.names.contains(s);
+        for pattern in &self.patterns {
+            if pattern.regex.is_match(s) {
+                pattern.matched.set(true);
+                matches = true;
+            }
+        }
+
+        matches
+    }
+
+    fn unmatched(&self) -> impl Iterator<Item = &str> {
+        self.patterns
+            .iter()
+            .filter(|pattern| !pattern.builtin && !pattern.matched.get())
+            .map(|pattern| pattern.source.as_str())
+    }
+}
+
+/// Returns whether an export list entry is a glob or regex pattern rather than a symbol name.
+fn is_pattern(symbol: &str) -> bool {
+    symbol.starts_with(REGEX_PREFIX) || symbol.contains(['*', '?'])
+}
+
+// `*` matches any sequence of characters, `?` any single character. The whole name must match.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut buf = [0u8; 4];
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut buf))),
+        }
+    }
+    regex.push('$');
+
+    regex
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set(entries: &[&str]) -> HashSet<String> {
+        entries.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn export_symbols(symbols: &[&str]) -> ExportSymbols {
+        ExportSymbols::new(&set(symbols), None, &HashSet::new()).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        let exports = export_symbols(&["foo", "bar.baz"]);
+        assert!(exports.contains("foo", None));
+        assert!(exports.contains("bar.baz", None));
+        assert!(!exports.contains("bar_baz", None));
+        assert!(!exports.contains("foobar", None));
+        // names aren't reported, only patterns
+        assert_eq!(exports.unmatched_symbols().count(), 0);
+    }
+
+    #[test]
+    fn test_globs() {
+        let exports = export_symbols(&["xdp_*", "tc_?gress", "kprobe.*"]);
+        assert!(exports.contains("xdp_", None));
+        assert!(exports.contains("xdp_pass", None));
+        assert!(!exports.contains("my_xdp_pass", None));
+        assert!(exports.contains("tc_egress", None));
+        assert!(!exports.contains("tc_ingress", None));
+        // `.` is literal
+        assert!(!exports.contains("kprobe_open", None));
+        assert_eq!(
+            exports.unmatched_symbols().collect::<Vec<_>>(),
+            ["kprobe.*"]
+        );
+    }
+
+    #[test]
+    fn test_regexes() {
+        let exports = export_symbols(&["re:^tc_(ingress|egress)$", "re:map", "re:^unused$"]);
+        assert!(exports.contains("tc_ingress", None));
+        assert!(exports.contains("tc_egress", None));
+        assert!(!exports.contains("tc_ingress2", None));
+        // regexes aren't anchored
+        assert!(exports.contains("EVENTS_map", None));
+        assert_eq!(
+            exports.unmatched_symbols().collect::<Vec<_>>(),
+            ["re:^unused$"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        assert!(matches!(
+            ExportSymbols::new(&set(&["re:("]), None, &HashSet::new()),
+            Err(LinkerError::InvalidExportPattern(pattern, _)) if pattern == "re:("
+        ));
+    }
+
+    #[test]
+    fn test_sections() {
+        let exports = export_symbols(&["foo"]);
+        assert!(!exports.contains("prog", Some("xdp")));
+
+        let exports = ExportSymbols::new(
+            &set(&["foo"]),
+            Some(&set(&["my_*", "unused/*"])),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert!(exports.contains("foo", None));
+        assert!(exports.contains("prog", Some("xdp")));
+        assert!(exports.contains("prog", Some("kprobe/do_sys_open")));
+        assert!(exports.contains("MAP", Some(".maps")));
+        assert!(exports.contains("_license", Some("license")));
+        assert!(exports.contains("prog", Some("my_section")));
+        assert!(!exports.contains("helper", Some(".text")));
+        assert!(!exports.contains("helper", None));
+        // `kprobe` needs the function name
+        assert!(!exports.contains("prog", Some("kprobe")));
+        // only the patterns passed by the user are reported
+        assert_eq!(
+            exports.unmatched_sections().collect::<Vec<_>>(),
+            ["unused/*"]
+        );
+        assert_eq!(exports.unmatched_symbols().count(), 0);
+    }
+
+    #[test]
+    fn test_local() {
+        let exports = ExportSymbols::new(
+            &set(&["prog_*", "prog_b"]),
+            Some(&HashSet::new()),
+            &set(&["prog_a", "prog_b", "MAP", "prog_x*"]),
+        )
+        .unwrap();
+        assert!(exports.contains("prog_c", None));
+        // local names and patterns win over patterns, including sections, but not over global
+        // names
+        assert!(!exports.contains("prog_a", None));
+        assert!(exports.contains("prog_b", None));
+        assert!(!exports.contains("MAP", Some(".maps")));
+        assert!(!exports.contains("prog_x1", None));
+    }
+}
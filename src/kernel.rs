@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use crate::{Cpu, LinkerError};
+
+/// What the BPF verifier and JIT of a range of kernel versions support.
+#[derive(Clone, Copy, Debug)]
+pub struct KernelProfile {
+    /// The oldest kernel, as `(major, minor)`, the profile applies to. It applies up to the next
+    /// profile in [`KERNEL_PROFILES`].
+    pub version: (u32, u32),
+    /// The newest instruction set the kernel understands.
+    pub cpu: Cpu,
+    /// BPF to BPF function calls. When not supported every function must be inlined.
+    pub bpf_calls: bool,
+    /// Bounded loops. When not supported every loop must be unrolled.
+    pub bounded_loops: bool,
+    /// Global variables in `.data`, `.rodata` and `.bss`.
+    pub global_data: bool,
+    /// Atomic operations other than add, like fetch and add, exchange and compare and exchange.
+    pub atomics: bool,
+    /// The number of instructions the verifier accepts in a program.
+    pub max_insns: u64,
+}
+
+/// The kernel profiles, oldest first. The versions are LTS kernels, the comments say what was
+/// added since the previous one.
+pub const KERNEL_PROFILES: &[KernelProfile] = &[
+    KernelProfile {
+        version: (4, 14),
+        cpu: Cpu::V2,
+        bpf_calls: false,
+        bounded_loops: false,
+        global_data: false,
+        atomics: false,
+        max_insns: 4096,
+    },
+    // BPF to BPF calls since 4.16
+    KernelProfile {
+        version: (4, 19),
+        cpu: Cpu::V2,
+        bpf_calls: true,
+        bounded_loops: false,
+        global_data: false,
+        atomics: false,
+        max_insns: 4096,
+    },
+    // v3 since 5.1, global data and 1M instructions since 5.2, bounded loops since 5.3
+    KernelProfile {
+        version: (5, 4),
+        cpu: Cpu::V3,
+        bpf_calls: true,
+        bounded_loops: true,
+        global_data: true,
+        atomics: false,
+        max_insns: 1_000_000,
+    },
+    KernelProfile {
+        version: (5, 10),
+        cpu: Cpu::V3,
+        bpf_calls: true,
+        bounded_loops: true,
+        global_data: true,
+        atomics: false,
+        max_insns: 1_000_000,
+    },
+    // atomics since 5.12
+    KernelProfile {
+        version: (5, 15),
+        cpu: Cpu::V3,
+        bpf_calls: true,
+        bounded_loops: true,
+        global_data: true,
+        atomics: true,
+        max_insns: 1_000_000,
+    },
+    KernelProfile {
+        version: (6, 1),
+        cpu: Cpu::V3,
+        bpf_calls: true,
+        bounded_loops: true,
+        global_data: true,
+        atomics: true,
+        max_insns: 1_000_000,
+    },
+];
+
+impl KernelProfile {
+    /// Returns the profile of the kernel `version`: the newest profile that isn't newer than it.
+    pub fn for_version(version: (u32, u32)) -> Option<KernelProfile> {
+        KERNEL_PROFILES
+            .iter()
+            .rev()
+            .find(|profile| profile.version <= version)
+            .copied()
+    }
+}
+
+impl std::fmt::Display for KernelProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (major, minor) = self.version;
+        write!(f, "{major}.{minor}")
+    }
+}
+
+impl FromStr for KernelProfile {
+    type Err = LinkerError;
+
+    /// Parses a kernel version like `5.10` or `5.15.0-91-generic` and returns its profile.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || LinkerError::InvalidKernelVersion(s.to_string());
+        let mut parts = s.splitn(3, '.');
+        let major = parts.next().and_then(|major| major.parse().ok());
+        let minor = parts.next().map(|minor| {
+            let end = minor
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(minor.len());
+            minor[..end].parse().ok()
+        });
+        match (major, minor) {
+            (Some(major), Some(Some(minor))) => {
+                KernelProfile::for_version((major, minor)).ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(s: &str) -> Option<(u32, u32)> {
+        s.parse::<KernelProfile>()
+            .ok()
+            .map(|profile| profile.version)
+    }
+
+    #[test]
+    fn test_profiles() {
+        assert!(KERNEL_PROFILES
+            .windows(2)
+            .all(|profiles| profiles[0].version < profiles[1].version));
+
+        assert_eq!(version("4.14"), Some((4, 14)));
+        assert_eq!(version("5.10"), Some((5, 10)));
+        // versions between profiles get the older one
+        assert_eq!(version("5.8"), Some((5, 4)));
+        assert_eq!(version("5.15.0-91-generic"), Some((5, 15)));
+        assert_eq!(version("6.1-rc1"), Some((6, 1)));
+        assert_eq!(version("6.8"), Some((6, 1)));
+        // too old
+        assert_eq!(version("4.9"), None);
+        assert_eq!(version("5"), None);
+        assert_eq!(version("5.x"), None);
+        assert_eq!(version("latest"), None);
+
+        let profile = "4.19".parse::<KernelProfile>().unwrap();
+        assert!(profile.bpf_calls && !profile.bounded_loops);
+        assert_eq!(profile.to_string(), "4.19");
+    }
+}
@@ -1,6 +1,18 @@
 #![deny(clippy::all)]
 
+mod analysis;
+mod archive;
+mod btf;
+mod call_graph;
+mod elf;
+mod export;
+mod kernel;
 mod linker;
 mod llvm;
+mod map;
+mod strip;
+mod trace;
+mod version_script;
 
+pub use kernel::*;
 pub use linker::*;
@@ -5,13 +5,17 @@ use llvm_sys::error_handling::*;
 use llvm_sys::prelude::*;
 use llvm_sys::target_machine::*;
 use log::*;
+use rustc_demangle::demangle;
 use std::{
-    collections::HashSet,
-    ffi::{CStr, CString},
-    fs::File,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ffi::{c_void, CStr, CString, OsStr},
+    fmt,
+    fs::{self, File},
     io,
     io::Read,
     io::Seek,
+    io::Write,
     path::Path,
     path::PathBuf,
     ptr, str,
@@ -19,7 +23,13 @@ use std::{
 };
 use thiserror::Error;
 
-use crate::llvm;
+use crate::{
+    analysis, archive, btf,
+    call_graph::{self, Rejected},
+    elf::{self, DecompressError},
+    export::ExportSymbols,
+    llvm, map, strip, trace, version_script, KernelProfile,
+};
 
 /// Linker error
 #[derive(Debug, Error)]
@@ -32,6 +42,10 @@ pub enum LinkerError {
     #[error("invalid LLVM target {0}")]
     InvalidTarget(String),
 
+    /// A kernel version that can't be parsed or is older than every profile.
+    #[error("invalid or unsupported kernel version {0}")]
+    InvalidKernelVersion(String),
+
     /// An IO Error occurred while linking a module.
     #[error("`{0}`: {1}")]
     IoError(PathBuf, io::Error),
@@ -40,17 +54,29 @@ pub enum LinkerError {
     #[error("invalid input file `{0}`")]
     InvalidInputType(PathBuf),
 
+    /// Parsing an LLVM IR input failed.
+    #[error("error parsing LLVM IR: {1}")]
+    IrParseError(PathBuf, String),
+
     /// Linking a module failed.
     #[error("failure linking module {0}")]
-    LinkModuleError(PathBuf),
+    LinkModuleError(PathBuf, Vec<Diagnostic>),
 
     /// Linking a module included in an archive failed.
-    #[error("failure linking module {1} from {0}")]
-    LinkArchiveModuleError(PathBuf, PathBuf),
+    #[error("failure linking module {0}({1})")]
+    LinkArchiveModuleError(PathBuf, PathBuf, Vec<Diagnostic>),
+
+    /// A symbol is defined by more than one input.
+    #[error("symbol `{0}` is defined in both {1} and {2}")]
+    SymbolMultiplyDefined(String, PathBuf, PathBuf),
+
+    /// An archive contains archives nested too deeply.
+    #[error("archive {0} is nested more than {MAX_ARCHIVE_DEPTH} levels deep")]
+    ArchiveTooDeep(PathBuf),
 
     /// Generating the BPF code failed.
     #[error("LLVMTargetMachineEmitToFile failed: {0}")]
-    EmitCodeError(String),
+    EmitCodeError(String, Vec<Diagnostic>),
 
     /// Writing the bitcode failed.
     #[error("LLVMWriteBitcodeToFile failed")]
@@ -64,9 +90,280 @@ pub enum LinkerError {
     #[error("error reading embedded bitcode: {0}")]
     EmbeddedBitcodeError(String),
 
+    /// The section containing the embedded bitcode is compressed in a format we don't support.
+    #[error("{0}: section `{1}` uses unsupported compression type {2}")]
+    UnsupportedCompression(PathBuf, String, u32),
+
     /// The input object file does not have embedded bitcode.
     #[error("no bitcode section found in {0}")]
     MissingBitcodeSection(PathBuf),
+
+    /// Rewriting the debug info into a form BTF supports failed.
+    #[error("rewriting debug info for BTF failed: {0}")]
+    DebugInfoRewriteError(String),
+
+    /// The debug info contains a type that LLVM's BTF generation can't handle.
+    #[error("`{0}`: debug info type `{1}` can't be converted to BTF: {2}")]
+    UnsupportedDebugInfo(String, String, String),
+
+    /// An export pattern isn't a valid regular expression.
+    #[error("invalid export pattern `{0}`: {1}")]
+    InvalidExportPattern(String, String),
+
+    /// The version script couldn't be parsed.
+    #[error("{0}:{1}: invalid version script: {2}")]
+    VersionScriptError(PathBuf, usize, String),
+
+    /// The deepest call chain of a program uses more stack than the verifier allows.
+    #[error("program `{0}` uses {1} bytes of stack, more than the limit of {2}: {3}")]
+    StackSizeLimitExceeded(String, u64, u64, String),
+
+    /// Functions exported or called by exported functions call themselves.
+    #[error("recursive calls aren't supported by the verifier: {0}")]
+    RecursiveCall(String),
+
+    /// An exported function calls, directly or not, a function through a pointer.
+    #[error(
+        "indirect calls aren't supported by the verifier: {0} calls a function pointer at {1}"
+    )]
+    IndirectCall(String, String),
+
+    /// The output uses something the kernel it targets doesn't support.
+    #[error("{0} uses {1}, which kernel {2} doesn't support")]
+    UnsupportedByKernel(String, String, String),
+
+    /// Removing sections or symbols from the output object failed.
+    #[error("error processing output object {0}: {1}")]
+    OutputObjectError(PathBuf, String),
+
+    /// Writing the map, the traced symbols or the other reports to the report writer failed.
+    #[error("error writing report: {0}")]
+    ReportError(io::Error),
+
+    /// A library passed with `-l` could not be found in any of the search paths.
+    #[error(
+        "unable to find library `{0}`, searched: [{}]",
+        .1.iter().map(|p| format!("{p:?}")).collect::<Vec<_>>().join(", ")
+    )]
+    LibraryNotFound(String, Vec<PathBuf>),
+}
+
+/// Exit code of the process when LLVM hits a fatal error. LLVM can't recover from fatal errors,
+/// so they can't be returned as a [`LinkerError`]: the error is reported to the diagnostic
+/// handler with the phase and the input being processed, printed to stderr, and the process
+/// exits.
+pub const LLVM_FATAL_EXIT_CODE: i32 = 3;
+
+impl LinkerError {
+    /// Returns the diagnostics reported by LLVM that led to the error.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            LinkerError::LinkModuleError(_, diagnostics)
+            | LinkerError::LinkArchiveModuleError(_, _, diagnostics)
+            | LinkerError::EmitCodeError(_, diagnostics) => diagnostics,
+            _ => &[],
+        }
+    }
+}
+
+/// Severity of a diagnostic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Remark,
+    Note,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Severity::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Error => "error",
+                Warning => "warning",
+                Remark => "remark",
+                Note => "note",
+            }
+        )
+    }
+}
+
+/// Phase of the linking process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Parsing the inputs.
+    Parse,
+    /// Linking the parsed inputs together.
+    Link,
+    /// Optimizing the linked module.
+    Optimize,
+    /// Generating the output.
+    Codegen,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Phase::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Parse => "parse",
+                Link => "link",
+                Optimize => "optimize",
+                Codegen => "codegen",
+            }
+        )
+    }
+}
+
+/// Location in the source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// A diagnostic reported by LLVM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The function the diagnostic is about.
+    pub function: Option<String>,
+    /// The source location the diagnostic is about. Only known when the inputs have debug info.
+    pub location: Option<SourceLocation>,
+    /// The phase the linker was in when the diagnostic was reported.
+    pub phase: Phase,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, phase: Phase, description: &str) -> Self {
+        let (location, function, message) = match parse_function_diagnostic(description) {
+            Some((location, function, message)) => (location, Some(function), message),
+            None => (None, None, description.to_owned()),
+        };
+
+        Diagnostic {
+            severity,
+            message,
+            function,
+            location,
+            phase,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(location) = &self.location {
+            write!(f, ": {location}")?;
+        }
+        if let Some(function) = &self.function {
+            write!(f, ": in function {function}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Callback invoked with every diagnostic reported while linking.
+pub struct DiagnosticHandler(pub Box<dyn FnMut(&Diagnostic)>);
+
+impl std::fmt::Debug for DiagnosticHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DiagnosticHandler")
+    }
+}
+
+/// Where the reports requested by the options are written: the map printed with `print_map`,
+/// the traced symbols, why symbols are live and what `print_gc_sections` removes.
+pub struct ReportWriter(pub Box<dyn Write>);
+
+impl std::fmt::Debug for ReportWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ReportWriter")
+    }
+}
+
+// Collects the diagnostics reported by LLVM through `llvm::diagnostic_handler`, and forwards them
+// to the user's handler. A pointer to it is passed as the handler context.
+pub(crate) struct Diagnostics {
+    phase: Cell<Phase>,
+    // the input being parsed or linked
+    input: RefCell<Option<PathBuf>>,
+    // diagnostics reported since the last call to take()
+    reported: RefCell<Vec<Diagnostic>>,
+    handler: RefCell<Option<DiagnosticHandler>>,
+}
+
+impl Diagnostics {
+    fn new(handler: Option<DiagnosticHandler>) -> Self {
+        Diagnostics {
+            phase: Cell::new(Phase::Parse),
+            input: RefCell::new(None),
+            reported: RefCell::new(Vec::new()),
+            handler: RefCell::new(handler),
+        }
+    }
+
+    pub(crate) fn report(&self, severity: Severity, description: &str) {
+        let diagnostic = Diagnostic::new(severity, self.phase.get(), description);
+        if let Some(DiagnosticHandler(handler)) = self.handler.borrow_mut().as_mut() {
+            handler(&diagnostic);
+        }
+        self.reported.borrow_mut().push(diagnostic);
+    }
+
+    // start a new phase, dropping the diagnostics reported so far
+    fn start(&self, phase: Phase) {
+        self.phase.set(phase);
+        self.reported.borrow_mut().clear();
+    }
+
+    fn take(&self) -> Vec<Diagnostic> {
+        self.reported.take()
+    }
+
+    // set the input the diagnostics are about until the returned guard is dropped, including
+    // when returning early with an error
+    fn set_input(&self, input: &Path) -> InputGuard<'_> {
+        *self.input.borrow_mut() = Some(input.to_owned());
+        InputGuard(self)
+    }
+
+    // report a fatal error, returning the message describing it
+    pub(crate) fn fatal(&self, reason: &str) -> String {
+        let message = match self.input.borrow().as_ref() {
+            Some(input) => format!(
+                "LLVM fatal error during {} of {}: {reason}",
+                self.phase.get(),
+                input.display()
+            ),
+            None => format!("LLVM fatal error during {}: {reason}", self.phase.get()),
+        };
+        self.report(Severity::Error, &message);
+
+        message
+    }
+}
+
+struct InputGuard<'a>(&'a Diagnostics);
+
+impl Drop for InputGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.input.borrow_mut() = None;
+    }
 }
 
 /// BPF Cpu type
@@ -134,6 +431,8 @@ enum InputType {
     Elf,
     /// Mach-O object file.
     MachO,
+    /// Textual LLVM IR. (.ll)
+    LlvmIr,
     /// Archive file. (.a)
     Archive,
 }
@@ -148,12 +447,43 @@ impl std::fmt::Display for InputType {
                 Bitcode => "bitcode",
                 Elf => "elf",
                 MachO => "Mach-O",
+                LlvmIr => "LLVM IR",
                 Archive => "archive",
             }
         )
     }
 }
 
+/// Where to find a linker input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputSource {
+    /// Path to bitcode, an object file with embedded bitcode or an archive file.
+    Path(PathBuf),
+    /// A library to look up in the library search paths. For each search path in order,
+    /// `lib<name>.a`, `lib<name>.rlib` and `<name>.bc` are tried. A name starting with `:` is
+    /// looked up as an exact file name.
+    Library(String),
+}
+
+/// Linker input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkerInput {
+    /// Where to find the input.
+    pub source: InputSource,
+    /// If the input is an archive, link all its members instead of only the members that define
+    /// an undefined symbol.
+    pub whole_archive: bool,
+}
+
+impl From<PathBuf> for LinkerInput {
+    fn from(path: PathBuf) -> Self {
+        LinkerInput {
+            source: InputSource::Path(path),
+            whole_archive: false,
+        }
+    }
+}
+
 /// Output type
 #[derive(Clone, Copy, Debug)]
 pub enum OutputType {
@@ -167,28 +497,62 @@ pub enum OutputType {
     Object,
 }
 
+/// Debug info to keep in the output. The BPF backend generates BTF from what is kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugInfo {
+    /// Strip all debug info.
+    None,
+    /// Keep the debug info of globals in the `.maps` section.
+    Maps,
+    /// Like `Maps`, and also keep the signatures of exported functions. The other functions are
+    /// described as `void()`.
+    Signatures,
+    /// Like `Maps`, and also keep the signatures and line info of every function. The types of
+    /// the signatures and maps end up in BTF, so they must be types BTF can describe.
+    Lines,
+    /// Only keep line info, describing every function as `void()`, without maps or any other
+    /// types. Equivalent to `-C debuginfo=line-tables-only`. Unlike `Lines`, it works whatever
+    /// the types in the debug info are, but the BTF has no function signatures or map types.
+    LineTablesOnly,
+    /// Keep all debug info.
+    Full,
+}
+
 /// Options to configure the linker
 #[derive(Debug)]
 pub struct LinkerOptions {
     /// The LLVM target to generate code for. If None, the target will be inferred from the input
     /// modules.
     pub target: Option<String>,
-    /// Cpu type.
-    pub cpu: Cpu,
+    /// Cpu type. `None` uses the processor of `kernel`, or [`Cpu::Generic`] without it.
+    pub cpu: Option<Cpu>,
     /// Cpu features.
     pub cpu_features: String,
-    /// Input files. Can be bitcode, object files with embedded bitcode or archive files.
-    pub inputs: Vec<PathBuf>,
+    /// Input files and libraries, in the order they are linked. Can be bitcode, object files with
+    /// embedded bitcode or archive files.
+    pub inputs: Vec<LinkerInput>,
     /// Where to save the output.
     pub output: PathBuf,
     /// The format to output.
     pub output_type: OutputType,
+    /// Library search paths.
     pub libs: Vec<PathBuf>,
     /// Optimization level.
     pub optimize: OptLevel,
-    /// Set of symbol names to export.
+    /// Set of symbols to export. Entries starting with `re:` are regular expressions, entries
+    /// containing `*` or `?` are globs matching whole names, anything else is a symbol name.
     pub export_symbols: HashSet<String>,
+    /// Export every function and global placed in a section libbpf and aya load programs, maps
+    /// and metadata from, like `xdp`, `kprobe/*`, `.maps` and `license`, and in the extra
+    /// sections given, which can be globs and `re:` regular expressions like `export_symbols`.
+    /// `None` exports by name only.
+    pub export_sections: Option<HashSet<String>>,
+    /// GNU ld version script listing the symbols to export under `global:`, in addition to
+    /// `export_symbols`. Symbols matching a name or glob under `local:` aren't exported even if
+    /// a glob matches them.
+    pub version_script: Option<PathBuf>,
     /// Whether to aggressively unroll loops. Useful for older kernels that don't support loops.
+    /// Always on when `kernel` doesn't support bounded loops.
     pub unroll_loops: bool,
     /// Remove `noinline` attributes from functions. Useful for kernels before 5.8 that don't
     /// support function calls.
@@ -203,6 +567,48 @@ pub struct LinkerOptions {
     /// those is commonly needed when LLVM does not manage to expand memory
     /// intrinsics to a sequence of loads and stores.
     pub disable_memory_builtins: bool,
+    /// Debug info to keep through optimization, so that the BPF backend emits BTF (`.BTF` and
+    /// `.BTF.ext`) for it.
+    pub debug_info: DebugInfo,
+    /// Remove the DWARF sections and the BTF line info from the output object.
+    pub strip_debug: bool,
+    /// Like `strip_debug`, and also remove the local symbols loaders don't need.
+    pub strip_all: bool,
+    /// Remove the sections of the output object nothing exported refers to.
+    pub gc_sections: bool,
+    /// Write a map of the output object to this file, listing the size of each section and, for
+    /// every symbol, its section, size, linkage and the input it came from.
+    pub map_file: Option<PathBuf>,
+    /// Print the map of the output object to `report_writer`.
+    pub print_map: bool,
+    /// Fail if a program's deepest chain of BPF to BPF calls uses more than this many bytes of
+    /// stack, counting every frame rounded up to 32 bytes like the verifier does. The verifier
+    /// limit is 512. `None` skips the check.
+    pub stack_size_limit: Option<u64>,
+    /// Warn when a program and the functions it calls add up to more than this many
+    /// instructions. `None` uses the limit of `kernel`, or the classic verifier limit of 4096
+    /// without it. 0 skips the check.
+    pub max_insns: Option<u64>,
+    /// The kernel the output must load on. Its profile fills in the options that aren't given:
+    /// `cpu` and `max_insns` when they're `None`. Loops are unrolled and
+    /// every function is inlined when the kernel doesn't support bounded loops or BPF to BPF
+    /// calls. Linking fails if the output still uses calls, loops, global data or atomic
+    /// operations the kernel doesn't support.
+    pub kernel: Option<KernelProfile>,
+    /// Print the functions, globals and aliases removed by optimization, with the input they came
+    /// from and their section, and the sections removed by `gc_sections`.
+    pub print_gc_sections: bool,
+    /// Print every input that defines or references these symbols, including the archive members
+    /// that aren't linked. Symbols can be mangled or demangled, with or without the hash.
+    pub trace_symbols: Vec<String>,
+    /// Print the chain of references from an exported symbol that keeps each of these symbols in
+    /// the output after optimization. Symbols can be mangled or demangled, like `trace_symbols`.
+    pub why_live: Vec<String>,
+    /// Called with every diagnostic LLVM reports.
+    pub diagnostic_handler: Option<DiagnosticHandler>,
+    /// Where the reports requested by `print_map`, `print_gc_sections`, `trace_symbols` and
+    /// `why_live` are written. Nothing is written when `None`.
+    pub report_writer: Option<ReportWriter>,
 }
 
 /// BPF Linker
@@ -211,32 +617,103 @@ pub struct Linker {
     context: LLVMContextRef,
     module: LLVMModuleRef,
     target_machine: LLVMTargetMachineRef,
+    // diagnostics reported by LLVM, boxed so the pointer given to LLVM stays valid
+    diagnostics: Box<Diagnostics>,
+    // symbol name -> input that defined it
+    symbol_origins: HashMap<String, PathBuf>,
+    // symbols with external linkage the linked module defines
+    defined: HashSet<String>,
+    // symbols the linked module references but doesn't define
+    undefined: HashSet<String>,
+    // the names and globs of the symbols the version script makes local
+    local_symbols: HashSet<String>,
+    // where the reports requested by the options are written
+    report_writer: RefCell<Option<ReportWriter>>,
 }
 
 impl Linker {
     /// Create a new linker instance with the given options.
-    pub fn new(options: LinkerOptions) -> Self {
+    pub fn new(mut options: LinkerOptions) -> Self {
+        let diagnostics = Box::new(Diagnostics::new(options.diagnostic_handler.take()));
+        let report_writer = RefCell::new(options.report_writer.take());
+        // the kernel profile only fills in what isn't given explicitly
+        if let Some(kernel) = options.kernel {
+            options.cpu = options.cpu.or(Some(kernel.cpu));
+            options.unroll_loops |= !kernel.bounded_loops;
+        }
+        options.max_insns = match options.max_insns {
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None => Some(
+                options
+                    .kernel
+                    .map_or(DEFAULT_MAX_INSNS, |kernel| kernel.max_insns),
+            ),
+        };
         Linker {
             options,
             context: ptr::null_mut(),
             module: ptr::null_mut(),
             target_machine: ptr::null_mut(),
+            diagnostics,
+            symbol_origins: HashMap::new(),
+            defined: HashSet::new(),
+            undefined: HashSet::new(),
+            local_symbols: HashSet::new(),
+            report_writer,
         }
     }
 
     /// Link and generate the output code.
+    ///
+    /// Errors are returned as [`LinkerError`], except fatal LLVM errors: LLVM can't continue
+    /// after them, so they terminate the process with [`LLVM_FATAL_EXIT_CODE`] once they have
+    /// been reported to the diagnostic handler and printed to stderr.
     pub fn link(mut self) -> Result<(), LinkerError> {
         self.llvm_init();
+        self.read_version_script()?;
         self.link_modules()?;
         self.create_target_machine()?;
         self.optimize()?;
         self.codegen()
     }
 
+    fn read_version_script(&mut self) -> Result<(), LinkerError> {
+        let path = match &self.options.version_script {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let script = fs::read_to_string(path).map_err(|e| LinkerError::IoError(path.clone(), e))?;
+        let script = version_script::parse(&script)
+            .map_err(|(line, e)| LinkerError::VersionScriptError(path.clone(), line, e))?;
+        self.options.export_symbols.extend(script.global);
+        // local names and globs take precedence over global globs but not over global names.
+        // `*` is the default anyway.
+        self.local_symbols
+            .extend(script.local.into_iter().filter(|s| s != "*"));
+
+        Ok(())
+    }
+
     fn link_modules(&mut self) -> Result<(), LinkerError> {
+        if !self.options.disable_memory_builtins {
+            // export the memory builtins, which also makes sure they get linked in from archives
+            self.options.export_symbols.extend(
+                ["memcpy", "memmove", "memset", "memcmp", "bcmp"]
+                    .iter()
+                    .map(|&s| s.to_owned()),
+            );
+        };
+
         // buffer used to perform file type detection
         let mut buf = [0u8; 8];
-        for path in self.options.inputs.clone() {
+        // archive members that get linked only if they define an undefined symbol
+        let mut lazy_members = Vec::new();
+        for input in self.options.inputs.clone() {
+            let path = match input.source {
+                InputSource::Path(path) => path,
+                InputSource::Library(name) => find_library(&name, &self.options.libs)?,
+            };
             let mut file = File::open(&path).map_err(|e| LinkerError::IoError(path.clone(), e))?;
 
             // determine whether the input is bitcode, ELF with embedded bitcode, an archive file
@@ -245,33 +722,34 @@ impl Linker {
                 .map_err(|e| LinkerError::IoError(path.clone(), e))?;
             file.rewind()
                 .map_err(|e| LinkerError::IoError(path.clone(), e))?;
-            let in_type = detect_input_type(&buf)
-                .ok_or_else(|| LinkerError::InvalidInputType(path.clone()))?;
+            let in_type = match detect_input_type(&buf) {
+                Some(in_type) => Some(in_type),
+                // textual IR doesn't have a magic number, look at the whole file. Files with the
+                // `.ll` extension are parsed whatever they contain, to report parse errors.
+                None => {
+                    let mut data = Vec::new();
+                    file.read_to_end(&mut data)
+                        .map_err(|e| LinkerError::IoError(path.clone(), e))?;
+                    file.rewind()
+                        .map_err(|e| LinkerError::IoError(path.clone(), e))?;
+                    detect_input_type(&data).or_else(|| {
+                        (path.extension() == Some(OsStr::new("ll"))).then_some(InputType::LlvmIr)
+                    })
+                }
+            }
+            .ok_or_else(|| LinkerError::InvalidInputType(path.clone()))?;
 
             match in_type {
+                InputType::Archive if !input.whole_archive => {
+                    info!("loading archive {:?}", path);
+                    let mut data = Vec::new();
+                    file.read_to_end(&mut data)
+                        .map_err(|e| LinkerError::IoError(path.clone(), e))?;
+                    lazy_members.extend(self.load_archive(&path, &data, 0)?);
+                }
                 InputType::Archive => {
                     info!("linking archive {:?}", path);
-
-                    // uncompress the archive and call link_reader() for each item
-                    let mut archive = Archive::new(file);
-                    while let Some(Ok(item)) = archive.next_entry() {
-                        let name =
-                            PathBuf::from(str::from_utf8(item.header().identifier()).unwrap());
-                        info!("linking archive item {:?}", name);
-
-                        match self.link_reader(&name, item, None) {
-                            Ok(_) => continue,
-                            Err(LinkerError::InvalidInputType(_)) => {
-                                info!("ignoring archive item {:?}: invalid type", name);
-                                continue;
-                            }
-                            Err(LinkerError::MissingBitcodeSection(_)) => {
-                                warn!("ignoring archive item {:?}: no embedded bitcode", name);
-                                continue;
-                            }
-                            Err(_) => return Err(LinkerError::LinkArchiveModuleError(path, name)),
-                        };
-                    }
+                    self.link_archive(&path, file, 0)?;
                 }
                 ty => {
                     info!("linking file {:?} type {}", path, ty);
@@ -290,6 +768,8 @@ impl Linker {
             }
         }
 
+        self.link_lazy_members(lazy_members)?;
+
         if let Some(path) = &self.options.dump_module {
             // dump IR for the final linked module for debugging purposes
             let path = CString::new(path.as_os_str().to_str().unwrap()).unwrap();
@@ -299,6 +779,239 @@ impl Linker {
         Ok(())
     }
 
+    // uncompress the archive and call link_reader() for each item. Archives nested inside the
+    // archive are linked recursively.
+    fn link_archive(
+        &mut self,
+        path: &Path,
+        reader: impl Read,
+        depth: usize,
+    ) -> Result<(), LinkerError> {
+        if depth > MAX_ARCHIVE_DEPTH {
+            return Err(LinkerError::ArchiveTooDeep(path.to_owned()));
+        }
+
+        let mut archive = Archive::new(reader);
+        while let Some(Ok(mut item)) = archive.next_entry() {
+            let name = PathBuf::from(str::from_utf8(item.header().identifier()).unwrap());
+            let mut data = Vec::new();
+            item.read_to_end(&mut data)
+                .map_err(|e| LinkerError::IoError(member_path(path, &name), e))?;
+
+            if detect_input_type(&data) == Some(InputType::Archive) {
+                info!("linking nested archive {:?}", member_path(path, &name));
+                self.link_archive(&member_path(path, &name), data.as_slice(), depth + 1)?;
+                continue;
+            }
+
+            info!("linking archive item {:?}", name);
+            match self.link_reader(&member_path(path, &name), data.as_slice(), None) {
+                Ok(_) => continue,
+                Err(LinkerError::InvalidInputType(_)) => {
+                    info!("ignoring archive item {:?}: invalid type", name);
+                    continue;
+                }
+                Err(LinkerError::MissingBitcodeSection(_)) => {
+                    warn!("ignoring archive item {:?}: no embedded bitcode", name);
+                    continue;
+                }
+                Err(e @ LinkerError::SymbolMultiplyDefined(..)) => return Err(e),
+                Err(e) => {
+                    return Err(LinkerError::LinkArchiveModuleError(
+                        path.to_owned(),
+                        name,
+                        e.diagnostics().to_vec(),
+                    ))
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    // read the members of an archive and the symbols they define, without linking them. Archives
+    // nested inside the archive are loaded recursively.
+    fn load_archive(
+        &self,
+        path: &Path,
+        data: &[u8],
+        depth: usize,
+    ) -> Result<Vec<LazyMember>, LinkerError> {
+        if depth > MAX_ARCHIVE_DEPTH {
+            return Err(LinkerError::ArchiveTooDeep(path.to_owned()));
+        }
+
+        // symbol name -> member index. The symbol table doesn't have the sections of the
+        // symbols, so it's ignored when exporting sections and every member gets parsed.
+        let symbol_table = match self.options.export_sections {
+            Some(_) => HashMap::new(),
+            None => archive::symbol_table(data).unwrap_or_default(),
+        };
+        let mut member_symbols = HashMap::<usize, HashMap<String, Option<String>>>::new();
+        for (symbol, index) in symbol_table {
+            member_symbols
+                .entry(index)
+                .or_default()
+                .insert(symbol, None);
+        }
+
+        let mut members = Vec::new();
+        let mut archive = Archive::new(data);
+        let mut index = 0;
+        while let Some(Ok(mut item)) = archive.next_entry() {
+            let name = PathBuf::from(str::from_utf8(item.header().identifier()).unwrap());
+            let mut data = Vec::new();
+            item.read_to_end(&mut data)
+                .map_err(|e| LinkerError::IoError(member_path(path, &name), e))?;
+
+            if detect_input_type(&data) == Some(InputType::Archive) {
+                info!("loading nested archive {:?}", member_path(path, &name));
+                members.extend(self.load_archive(&member_path(path, &name), &data, depth + 1)?);
+                index += 1;
+                continue;
+            }
+
+            let symbols = match member_symbols.remove(&index) {
+                Some(symbols) => symbols,
+                // the member isn't in the symbol table, either because there's no symbol table,
+                // because the tool that created the archive doesn't understand bitcode or because
+                // the sections are needed
+                None => match self.member_symbols(&name, &data) {
+                    Ok(symbols) => symbols,
+                    Err(LinkerError::InvalidInputType(_)) => {
+                        info!("ignoring archive item {:?}: invalid type", name);
+                        HashMap::new()
+                    }
+                    Err(LinkerError::MissingBitcodeSection(_)) => {
+                        warn!("ignoring archive item {:?}: no embedded bitcode", name);
+                        HashMap::new()
+                    }
+                    Err(e) => {
+                        return Err(LinkerError::LinkArchiveModuleError(
+                            path.to_owned(),
+                            name,
+                            e.diagnostics().to_vec(),
+                        ))
+                    }
+                },
+            };
+            index += 1;
+
+            if !symbols.is_empty() {
+                members.push(LazyMember {
+                    archive: path.to_owned(),
+                    name,
+                    data,
+                    symbols,
+                    linked: false,
+                });
+            }
+        }
+
+        Ok(members)
+    }
+
+    // find the symbols defined by an archive member and their sections, for members that aren't
+    // listed in the symbol table
+    fn member_symbols(
+        &self,
+        name: &Path,
+        data: &[u8],
+    ) -> Result<HashMap<String, Option<String>>, LinkerError> {
+        if detect_input_type(data) == Some(InputType::LlvmIr) {
+            self.diagnostics.start(Phase::Parse);
+            let _input = self.diagnostics.set_input(name);
+            return unsafe {
+                llvm::ir_defined_symbols(self.context, &name.to_string_lossy(), data)
+            }
+            .map_err(|e| LinkerError::IrParseError(name.to_owned(), e));
+        }
+
+        let bitcode = self.bitcode(name, data.to_vec(), None)?;
+        self.diagnostics.start(Phase::Parse);
+        let _input = self.diagnostics.set_input(name);
+        unsafe { llvm::bitcode_defined_symbols(self.context, &bitcode) }
+            .ok_or_else(|| LinkerError::LinkModuleError(name.to_owned(), self.diagnostics.take()))
+    }
+
+    // link the archive members that define undefined or exported symbols, until there are no
+    // more members that can resolve any of the remaining undefined symbols
+    fn link_lazy_members(&mut self, mut members: Vec<LazyMember>) -> Result<(), LinkerError> {
+        let exports = ExportSymbols::new(
+            &self.options.export_symbols,
+            self.options.export_sections.as_ref(),
+            &self.local_symbols,
+        )?;
+        loop {
+            let mut changed = false;
+            for member in members.iter_mut() {
+                if member.linked || !self.resolves_undefined(member, &exports) {
+                    continue;
+                }
+                member.linked = true;
+                changed = true;
+
+                info!(
+                    "linking archive item {:?} from {:?}",
+                    member.name, member.archive
+                );
+                let path = member_path(&member.archive, &member.name);
+                match self.link_reader(&path, member.data.as_slice(), None) {
+                    Ok(_) => {}
+                    Err(LinkerError::InvalidInputType(_)) => {
+                        info!("ignoring archive item {:?}: invalid type", member.name);
+                    }
+                    Err(LinkerError::MissingBitcodeSection(_)) => {
+                        warn!(
+                            "ignoring archive item {:?}: no embedded bitcode",
+                            member.name
+                        );
+                    }
+                    Err(e @ LinkerError::SymbolMultiplyDefined(..)) => return Err(e),
+                    Err(e) => {
+                        return Err(LinkerError::LinkArchiveModuleError(
+                            member.archive.clone(),
+                            member.name.clone(),
+                            e.diagnostics().to_vec(),
+                        ))
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        // the traced symbols are also reported when the member defining them isn't linked
+        for member in members.iter().filter(|member| !member.linked) {
+            let mut symbols = member
+                .symbols
+                .keys()
+                .filter(|symbol| self.is_traced(symbol))
+                .collect::<Vec<_>>();
+            symbols.sort();
+            for symbol in symbols {
+                self.report(format_args!(
+                    "{}: definition of {}, not linked",
+                    member_path(&member.archive, &member.name).display(),
+                    symbol
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // whether `member` defines a symbol that is referenced or exported but not defined by the
+    // linked module
+    fn resolves_undefined(&self, member: &LazyMember, exports: &ExportSymbols) -> bool {
+        member.symbols.iter().any(|(symbol, section)| {
+            self.undefined.contains(symbol)
+                || (!self.defined.contains(symbol) && exports.contains(symbol, section.as_deref()))
+        })
+    }
+
     // link in a `Read`-er, which can be a file or an archive item
     fn link_reader(
         &mut self,
@@ -311,33 +1024,179 @@ impl Linker {
             .read_to_end(&mut data)
             .map_err(|e| LinkerError::IoError(path.to_owned(), e))?;
         // in_type is unknown when we're linking an item from an archive file
+        let in_type = in_type.or_else(|| detect_input_type(&data));
+        self.diagnostics.start(Phase::Parse);
+        let input = self.diagnostics.set_input(path);
+        let module = if in_type == Some(InputType::LlvmIr) {
+            let name = path.to_string_lossy();
+            unsafe { llvm::parse_ir(self.context, &name, &data) }
+                .map_err(|e| LinkerError::IrParseError(path.to_owned(), e))?
+        } else {
+            let bitcode = self.bitcode(path, data, in_type)?;
+            unsafe { llvm::parse_bitcode(self.context, &bitcode) }.ok_or_else(|| {
+                LinkerError::LinkModuleError(path.to_owned(), self.diagnostics.take())
+            })?
+        };
+
+        self.trace_symbols(path, module)?;
+
+        // LLVM renames the internal symbols whose names clash with the symbols already linked,
+        // rename them first so that the symbols and where they come from can be recorded
+        // without rescanning the linked module
+        let symbols = unsafe {
+            llvm::rename_local_symbols(module, |name| {
+                self.symbol_origins.contains_key(name) || self.undefined.contains(name)
+            });
+            llvm::symbols(module)
+        };
+        // LLVM also renames the internal symbols of the linked module whose names clash with the
+        // external symbols of `module`, rename them first to keep track of where they come from
+        for symbol in symbols.iter().filter(|symbol| !symbol.local) {
+            let new_name =
+                match unsafe { llvm::rename_local_symbol(self.module, module, &symbol.name) } {
+                    Some(new_name) => new_name,
+                    None => continue,
+                };
+            if let Some(origin) = self.symbol_origins.remove(&symbol.name) {
+                self.symbol_origins.insert(new_name, origin);
+            }
+        }
+
+        self.diagnostics.start(Phase::Link);
+        if !unsafe { llvm::link_module(self.module, module) } {
+            let diagnostics = self.diagnostics.take();
+            if let Some((symbol, first)) = diagnostics.iter().find_map(|d| {
+                let symbol = multiply_defined_symbol(&d.message)?;
+                Some((symbol, self.symbol_origins.get(symbol)?))
+            }) {
+                return Err(LinkerError::SymbolMultiplyDefined(
+                    symbol.to_owned(),
+                    first.clone(),
+                    path.to_owned(),
+                ));
+            }
+            return Err(LinkerError::LinkModuleError(path.to_owned(), diagnostics));
+        }
+
+        drop(input);
+        self.add_symbols(path, symbols);
+
+        Ok(())
+    }
+
+    // records the symbols of the module linked from `path`. Where symbols come from is used to
+    // report duplicate definitions and removed symbols, internal symbols are included.
+    fn add_symbols(&mut self, path: &Path, symbols: Vec<llvm::Symbol>) {
+        for symbol in symbols {
+            if !symbol.defined {
+                if !self.defined.contains(&symbol.name) {
+                    self.undefined.insert(symbol.name);
+                }
+                continue;
+            }
+            if !symbol.local {
+                self.undefined.remove(&symbol.name);
+                self.defined.insert(symbol.name.clone());
+            }
+            self.symbol_origins
+                .entry(symbol.name)
+                .or_insert_with(|| path.to_owned());
+        }
+    }
+
+    // get the bitcode from the data of an input file or archive item
+    fn bitcode(
+        &self,
+        path: &Path,
+        data: Vec<u8>,
+        in_type: Option<InputType>,
+    ) -> Result<Vec<u8>, LinkerError> {
+        // in_type is unknown when we're linking an item from an archive file
         let in_type = in_type
             .or_else(|| detect_input_type(&data))
             .ok_or_else(|| LinkerError::InvalidInputType(path.to_owned()))?;
 
         use InputType::*;
-        let bitcode = match in_type {
+        Ok(match in_type {
             Bitcode => data,
             Elf => match unsafe { llvm::find_embedded_bitcode(self.context, &data) } {
-                Ok(Some(bitcode)) => bitcode,
+                Ok(Some((section, bitcode))) => match elf::Elf::parse(&data) {
+                    Some(elf) if elf.is_compressed(&section) => {
+                        elf.decompress(&bitcode).map_err(|e| match e {
+                            DecompressError::UnsupportedCompression(ty) => {
+                                LinkerError::UnsupportedCompression(path.to_owned(), section, ty)
+                            }
+                            DecompressError::InvalidData(e) => LinkerError::EmbeddedBitcodeError(
+                                format!("{}: section `{}`: {}", path.display(), section, e),
+                            ),
+                        })?
+                    }
+                    _ => bitcode,
+                },
                 Ok(None) => return Err(LinkerError::MissingBitcodeSection(path.to_owned())),
                 Err(e) => return Err(LinkerError::EmbeddedBitcodeError(e)),
             },
-            // we need to handle this here since archive files could contain
-            // mach-o files, eg somecrate.rlib containing lib.rmeta which is
-            // mach-o on macos
-            InputType::MachO => return Err(LinkerError::InvalidInputType(path.to_owned())),
-            // this can't really happen
-            Archive => panic!("nested archives not supported duh"),
-        };
+            // universal binaries contain one object per architecture, use the bitcode from the
+            // first one that has it
+            MachO => {
+                let objects = macho_universal_objects(&data).unwrap_or_else(|| vec![&data]);
+                let mut bitcode = None;
+                for object in objects {
+                    match unsafe { llvm::find_embedded_bitcode(self.context, object) } {
+                        Ok(Some((_, b))) => {
+                            bitcode = Some(b);
+                            break;
+                        }
+                        Ok(None) => continue,
+                        Err(e) => return Err(LinkerError::EmbeddedBitcodeError(e)),
+                    }
+                }
+                // archive files can contain mach-o files without bitcode, eg somecrate.rlib
+                // containing lib.rmeta which is mach-o on macos
+                bitcode.ok_or_else(|| LinkerError::MissingBitcodeSection(path.to_owned()))?
+            }
+            // archives, including nested ones, are handled by link_archive() and load_archive(),
+            // and textual IR by link_reader()
+            Archive | LlvmIr => return Err(LinkerError::InvalidInputType(path.to_owned())),
+        })
+    }
 
-        if unsafe { !llvm::link_bitcode_buffer(self.context, self.module, &bitcode) } {
-            return Err(LinkerError::LinkModuleError(path.to_owned()));
+    fn trace_symbols(&self, path: &Path, module: LLVMModuleRef) -> Result<(), LinkerError> {
+        if self.options.trace_symbols.is_empty() {
+            return Ok(());
+        }
+        for (symbol, defined) in unsafe { llvm::symbol_definitions(module) } {
+            if self.is_traced(&symbol) {
+                let kind = if defined {
+                    "definition of"
+                } else {
+                    "reference to"
+                };
+                self.report(format_args!("{}: {} {}", path.display(), kind, symbol))?;
+            }
         }
 
         Ok(())
     }
 
+    // writes a line of a report requested by the options to the report writer
+    fn report(&self, line: fmt::Arguments) -> Result<(), LinkerError> {
+        match &mut *self.report_writer.borrow_mut() {
+            Some(ReportWriter(writer)) => writeln!(writer, "{line}")
+                .and_then(|_| writer.flush())
+                .map_err(LinkerError::ReportError),
+            None => Ok(()),
+        }
+    }
+
+    // whether `symbol` is one of the symbols to trace
+    fn is_traced(&self, symbol: &str) -> bool {
+        self.options
+            .trace_symbols
+            .iter()
+            .any(|name| trace::symbol_matches(symbol, name))
+    }
+
     fn create_target_machine(&mut self) -> Result<(), LinkerError> {
         unsafe {
             // Here's how the output target is selected:
@@ -377,60 +1236,203 @@ impl Linker {
             };
             let target = target.map_err(|_msg| LinkerError::InvalidTarget(triple.clone()))?;
 
+            let cpu = self.options.cpu.unwrap_or(Cpu::Generic).to_string();
             debug!(
                 "creating target machine: triple: {} cpu: {} features: {}",
-                triple,
-                self.options.cpu.to_string(),
-                self.options.cpu_features
+                triple, cpu, self.options.cpu_features
             );
 
-            self.target_machine = llvm::create_target_machine(
-                target,
-                &triple,
-                &self.options.cpu.to_string(),
-                &self.options.cpu_features,
-            )
-            .ok_or(LinkerError::InvalidTarget(triple))?;
+            self.target_machine =
+                llvm::create_target_machine(target, &triple, &cpu, &self.options.cpu_features)
+                    .ok_or(LinkerError::InvalidTarget(triple))?;
+
+            // in cases 2 and 3 the module is still configured for the host, configure it for the
+            // output target so that optimization and BTF generation, which keeps the line info
+            // of `--debuginfo line-tables-only`, see the BPF data layout
+            llvm::set_target(self.module, self.target_machine);
         }
 
         Ok(())
     }
 
     fn optimize(&mut self) -> Result<(), LinkerError> {
-        if !self.options.disable_memory_builtins {
-            self.options.export_symbols.extend(
-                ["memcpy", "memmove", "memset", "memcmp", "bcmp"]
-                    .iter()
-                    .map(|&s| s.to_owned()),
-            );
-        };
         debug!(
             "linking exporting symbols {:?}, opt level {:?}",
             self.options.export_symbols, self.options.optimize
         );
-        // run optimizations. Will optionally remove noinline attributes, intern all non exported
-        // programs and maps and remove dead code.
+        // run optimizations. Will optionally remove noinline attributes or inline every function,
+        // intern all non exported programs and maps and remove dead code.
+        let export_symbols = ExportSymbols::new(
+            &self.options.export_symbols,
+            self.options.export_sections.as_ref(),
+            &self.local_symbols,
+        )?;
+        let linked = if self.options.print_gc_sections {
+            unsafe { llvm::definitions(self.module) }
+        } else {
+            Vec::new()
+        };
+        self.diagnostics.start(Phase::Optimize);
         unsafe {
             llvm::optimize(
                 self.target_machine,
                 self.module,
                 self.options.optimize,
                 self.options.ignore_inline_never,
-                &self.options.export_symbols,
+                self.options.kernel.is_some_and(|kernel| !kernel.bpf_calls),
+                &export_symbols,
+                self.options.debug_info,
             )
         };
+        for pattern in export_symbols.unmatched_symbols() {
+            warn!("export pattern `{}` didn't match any symbol", pattern);
+        }
+        for pattern in export_symbols.unmatched_sections() {
+            warn!(
+                "export section pattern `{}` didn't match any section",
+                pattern
+            );
+        }
+        self.why_live()?;
+        if self.options.print_gc_sections {
+            self.print_removed_definitions(linked)?;
+        }
+        self.check_calls()?;
+
+        if self.options.debug_info != DebugInfo::None
+            && (self.options.strip_debug || self.options.strip_all)
+        {
+            unsafe { llvm::strip_line_info(self.module) };
+        }
+
+        if self.options.debug_info != DebugInfo::None {
+            // rustc emits debug info that LLVM's BTF generation can't handle, rewrite it
+            let level = self.options.debug_info;
+            let module = unsafe {
+                llvm::rewrite_ir(self.context, self.module, |ir| {
+                    btf::rewrite_debug_info(ir, level)
+                })
+                .map_err(LinkerError::DebugInfoRewriteError)?
+            };
+            unsafe { LLVMDisposeModule(self.module) };
+            self.module = module;
+        }
+
+        Ok(())
+    }
+
+    // prints the definitions in `linked` that optimization removed from the module
+    fn print_removed_definitions(&self, linked: Vec<llvm::Definition>) -> Result<(), LinkerError> {
+        let remaining = unsafe { llvm::definitions(self.module) }
+            .into_iter()
+            .map(|definition| definition.name)
+            .collect::<HashSet<_>>();
+        for definition in linked {
+            if remaining.contains(&definition.name) {
+                continue;
+            }
+            let section = match &definition.section {
+                Some(section) => format!(" in section `{section}`"),
+                None => String::new(),
+            };
+            let origin = match self.symbol_origins.get(&definition.name) {
+                Some(path) => format!(" from {}", path.display()),
+                None => String::new(),
+            };
+            self.report(format_args!(
+                "removing unused {} `{}`{}{}",
+                definition.kind, definition.name, section, origin
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    // fails if what's exported recurses or calls function pointers, which the verifier rejects.
+    // Only BPF code emitted as an object or assembly gets to the verifier.
+    fn check_calls(&self) -> Result<(), LinkerError> {
+        // the module is configured for the target machine by now
+        let triple = unsafe { CStr::from_ptr(LLVMGetTarget(self.module)) };
+        let emits_code = matches!(
+            self.options.output_type,
+            OutputType::Object | OutputType::Assembly
+        );
+        if !triple.to_bytes().starts_with(b"bpf") || !emits_code {
+            return Ok(());
+        }
+        let calls = unsafe { llvm::calls(self.module) };
+        let roots = unsafe { llvm::defined_symbols(self.module) };
+        let chain = |functions: &[String]| {
+            functions
+                .iter()
+                .map(|function| format!("`{:#}`", demangle(function)))
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        };
+        match call_graph::check_calls(&roots, &calls) {
+            Ok(()) => Ok(()),
+            Err(Rejected::Recursion(cycle)) => Err(LinkerError::RecursiveCall(chain(&cycle))),
+            Err(Rejected::IndirectCall(callers, site)) => {
+                Err(LinkerError::IndirectCall(chain(&callers), site))
+            }
+        }
+    }
+
+    // prints why the symbols in `why_live` are still in the module after optimization
+    fn why_live(&self) -> Result<(), LinkerError> {
+        if self.options.why_live.is_empty() {
+            return Ok(());
+        }
+        let references = unsafe { llvm::symbol_references(self.module) };
+        let roots = unsafe { llvm::defined_symbols(self.module) };
+        let mut symbols = unsafe { llvm::symbol_definitions(self.module) };
+        symbols.sort();
+        for name in &self.options.why_live {
+            let mut found = false;
+            for (symbol, _) in symbols
+                .iter()
+                .filter(|(symbol, _)| trace::symbol_matches(symbol, name))
+            {
+                found = true;
+                match trace::why_live(&roots, &references, symbol) {
+                    Some(mut chain) => {
+                        // the other roots are `llvm.used` and `llvm.compiler.used`
+                        if !chain[0].starts_with("llvm.") {
+                            chain[0].push_str(" (exported)");
+                        }
+                        self.report(format_args!("{} is live: {}", symbol, chain.join(" -> ")))?;
+                    }
+                    None => self.report(format_args!(
+                        "{symbol} isn't referenced by any exported symbol"
+                    ))?,
+                }
+            }
+            if !found {
+                self.report(format_args!("{name} isn't in the output"))?;
+            }
+        }
 
         Ok(())
     }
 
     fn codegen(&mut self) -> Result<(), LinkerError> {
         let output = CString::new(self.options.output.as_os_str().to_str().unwrap()).unwrap();
+        self.diagnostics.start(Phase::Codegen);
+
+        if (self.options.map_file.is_some() || self.options.print_map)
+            && !matches!(self.options.output_type, OutputType::Object)
+        {
+            warn!("not writing a map, the output isn't an object file");
+        }
 
         match self.options.output_type {
             OutputType::Bitcode => self.write_bitcode(&output),
             OutputType::LlvmAssembly => self.write_ir(&output),
             OutputType::Assembly => self.emit(&output, LLVMCodeGenFileType::LLVMAssemblyFile),
-            OutputType::Object => self.emit(&output, LLVMCodeGenFileType::LLVMObjectFile),
+            OutputType::Object => {
+                self.emit(&output, LLVMCodeGenFileType::LLVMObjectFile)?;
+                self.process_object()
+            }
         }
     }
 
@@ -451,10 +1453,120 @@ impl Linker {
     }
 
     fn emit(&mut self, output: &CStr, output_type: LLVMCodeGenFileType) -> Result<(), LinkerError> {
+        if self.options.debug_info != DebugInfo::None {
+            // fail cleanly on the debug info that would crash the BPF backend
+            if let Some(ty) = unsafe { llvm::find_unsupported_btf_type(self.context, self.module) }
+            {
+                return Err(LinkerError::UnsupportedDebugInfo(
+                    ty.symbol,
+                    ty.type_name,
+                    ty.reason,
+                ));
+            }
+        }
+
         info!("emitting {:?} to {:?}", output_type, output);
 
         unsafe { llvm::codegen(self.target_machine, self.module, output, output_type) }
-            .map_err(LinkerError::EmitCodeError)
+            .map_err(|e| LinkerError::EmitCodeError(e, self.diagnostics.take()))
+    }
+
+    // post-processes the emitted object: removes sections and symbols, checks the stack usage
+    // and size of the programs and writes the map
+    fn process_object(&mut self) -> Result<(), LinkerError> {
+        let LinkerOptions {
+            strip_debug,
+            strip_all,
+            gc_sections,
+            stack_size_limit,
+            max_insns,
+            kernel,
+            print_map,
+            ..
+        } = self.options;
+        let strip = strip_debug || strip_all || gc_sections;
+        let map = print_map || self.options.map_file.is_some();
+        let check = stack_size_limit.is_some() || max_insns.is_some() || kernel.is_some();
+        if !(strip || map || check) {
+            return Ok(());
+        }
+
+        let path = &self.options.output;
+        let data = fs::read(path).map_err(|e| LinkerError::IoError(path.clone(), e))?;
+        let mut object = match elf::Object::parse(&data) {
+            Ok(object) => object,
+            // the program checks are on by default, don't fail links that don't ask for more
+            Err(e) if !(strip || map || kernel.is_some()) => {
+                warn!("not checking the programs of {}: {}", path.display(), e);
+                return Ok(());
+            }
+            Err(e) => return Err(LinkerError::OutputObjectError(path.clone(), e)),
+        };
+        if strip {
+            self.strip_object(&mut object)?;
+            fs::write(path, object.write()).map_err(|e| LinkerError::IoError(path.clone(), e))?;
+        }
+        if map {
+            self.write_map(&object)?;
+        }
+        if let Some(limit) = stack_size_limit {
+            check_stack_usage(&object, limit)?;
+        }
+        if let Some(limit) = max_insns {
+            check_program_sizes(&object, limit);
+        }
+        if let Some(kernel) = kernel {
+            check_kernel_support(&object, &kernel)?;
+        }
+
+        Ok(())
+    }
+
+    fn strip_object(&self, object: &mut elf::Object) -> Result<(), LinkerError> {
+        let LinkerOptions {
+            strip_debug,
+            strip_all,
+            gc_sections,
+            ..
+        } = self.options;
+        if gc_sections {
+            for section in strip::gc_sections(object) {
+                debug!("removed unused section {}", section);
+                if self.options.print_gc_sections {
+                    self.report(format_args!(
+                        "removing unused section `{}` from {}",
+                        section,
+                        self.options.output.display()
+                    ))?;
+                }
+            }
+        }
+        if strip_debug || strip_all {
+            strip::strip_debug(object);
+        }
+        if strip_all {
+            strip::strip_symbols(object);
+        }
+
+        Ok(())
+    }
+
+    fn write_map(&self, object: &elf::Object) -> Result<(), LinkerError> {
+        if let Some(map_file) = &self.options.map_file {
+            let mut file =
+                File::create(map_file).map_err(|e| LinkerError::IoError(map_file.clone(), e))?;
+            map::write_map(object, &self.symbol_origins, &mut file)
+                .map_err(|e| LinkerError::IoError(map_file.clone(), e))?;
+        }
+        if self.options.print_map {
+            if let Some(ReportWriter(writer)) = &mut *self.report_writer.borrow_mut() {
+                map::write_map(object, &self.symbol_origins, writer)
+                    .and_then(|_| writer.flush())
+                    .map_err(LinkerError::ReportError)?;
+            }
+        }
+
+        Ok(())
     }
 
     fn llvm_init(&mut self) {
@@ -479,8 +1591,9 @@ impl Linker {
             LLVMContextSetDiagnosticHandler(
                 self.context,
                 Some(llvm::diagnostic_handler),
-                ptr::null_mut(),
+                &*self.diagnostics as *const _ as *mut c_void,
             );
+            llvm::set_fatal_error_diagnostics(&self.diagnostics);
             LLVMInstallFatalErrorHandler(Some(llvm::fatal_error));
             LLVMEnablePrettyStackTrace();
             self.module = llvm::create_module(
@@ -494,6 +1607,7 @@ impl Linker {
 
 impl Drop for Linker {
     fn drop(&mut self) {
+        llvm::clear_fatal_error_diagnostics(&self.diagnostics);
         unsafe {
             if !self.target_machine.is_null() {
                 LLVMDisposeTargetMachine(self.target_machine);
@@ -508,6 +1622,208 @@ impl Drop for Linker {
     }
 }
 
+// Returns the symbol named in the error LLVM reports when two modules define the same symbol:
+// "Linking globals named 'foo': symbol multiply defined!"
+fn multiply_defined_symbol(error: &str) -> Option<&str> {
+    error
+        .strip_prefix("Linking globals named '")?
+        .strip_suffix("': symbol multiply defined!")
+}
+
+// Splits the description of diagnostics about a function, like the ones reported by the BPF
+// backend, formatted as "<file>:<line>:<column>: in function <name> <type>: <message>". The
+// location is "<unknown>:0:0" when there's no debug info.
+fn parse_function_diagnostic(
+    description: &str,
+) -> Option<(Option<SourceLocation>, String, String)> {
+    let (location, rest) = description.split_once(": in function ")?;
+    let (function, rest) = rest.split_once(' ')?;
+    let (_, message) = rest.split_once(": ")?;
+
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+    let location = (line != 0).then(|| SourceLocation {
+        file: file.to_owned(),
+        line,
+        column,
+    });
+
+    Some((location, function.to_owned(), message.to_owned()))
+}
+
+/// Maximum depth of archives nested inside archives.
+const MAX_ARCHIVE_DEPTH: usize = 8;
+
+/// The instructions limit of programs when no kernel is given, the classic verifier limit.
+const DEFAULT_MAX_INSNS: u64 = 4096;
+
+// fails if a program's deepest call chain uses more than `limit` bytes of stack
+fn check_stack_usage(object: &elf::Object, limit: u64) -> Result<(), LinkerError> {
+    if object.machine != elf::EM_BPF {
+        return Ok(());
+    }
+    for program in analysis::stack_usage(object) {
+        let chain = program
+            .chain
+            .iter()
+            .map(|(name, frame_size)| format!("{name} ({frame_size} bytes)"))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        info!(
+            "program `{}` uses up to {} bytes of stack: {}",
+            program.name, program.depth, chain
+        );
+        if program.depth > limit {
+            return Err(LinkerError::StackSizeLimitExceeded(
+                program.name,
+                program.depth,
+                limit,
+                chain,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// warns about the programs that, with the functions they call, have more than `limit`
+// instructions
+fn check_program_sizes(object: &elf::Object, limit: u64) {
+    if object.machine != elf::EM_BPF {
+        return;
+    }
+    for program in analysis::program_sizes(object) {
+        info!(
+            "program `{}`: {} instructions, {} branches, {} loops",
+            program.name, program.insns, program.branches, program.loops
+        );
+        if program.insns > limit {
+            warn!(
+                "program `{}` has {} instructions, more than the limit of {}",
+                program.name, program.insns, limit
+            );
+        }
+    }
+}
+
+// fails if the programs of `object` use calls, loops, global data or atomics `kernel` doesn't
+// support
+fn check_kernel_support(object: &elf::Object, kernel: &KernelProfile) -> Result<(), LinkerError> {
+    if object.machine != elf::EM_BPF {
+        return Ok(());
+    }
+    let unsupported = |what: String, feature: &str| {
+        LinkerError::UnsupportedByKernel(what, feature.to_string(), kernel.to_string())
+    };
+    if !kernel.global_data {
+        if let Some(section) = object.sections.iter().find(|section| {
+            section.flags & elf::SHF_ALLOC != 0
+                && [".data", ".rodata", ".bss"].iter().any(|prefix| {
+                    section.name == *prefix || section.name.starts_with(&format!("{prefix}."))
+                })
+        }) {
+            return Err(unsupported(
+                format!("section `{}`", section.name),
+                "global data",
+            ));
+        }
+    }
+    for program in analysis::program_sizes(object) {
+        let what = || format!("program `{}`", program.name);
+        if !kernel.bpf_calls && program.calls > 0 {
+            return Err(unsupported(what(), "BPF to BPF calls"));
+        }
+        if !kernel.bounded_loops && program.loops > 0 {
+            return Err(unsupported(what(), "loops"));
+        }
+        if !kernel.atomics && program.atomics > 0 {
+            return Err(unsupported(what(), "atomic operations"));
+        }
+    }
+
+    Ok(())
+}
+
+// the path of an archive member, in the `archive.a(member.o)` format
+fn member_path(archive: &Path, member: &Path) -> PathBuf {
+    PathBuf::from(format!("{}({})", archive.display(), member.display()))
+}
+
+// An archive member that gets linked only if it defines an undefined symbol
+struct LazyMember {
+    archive: PathBuf,
+    name: PathBuf,
+    data: Vec<u8>,
+    // the symbols the member defines and their sections. The sections are only known when
+    // exporting sections.
+    symbols: HashMap<String, Option<String>>,
+    linked: bool,
+}
+
+fn find_library(name: &str, search_paths: &[PathBuf]) -> Result<PathBuf, LinkerError> {
+    let file_names = match name.strip_prefix(':') {
+        Some(file_name) => vec![file_name.to_owned()],
+        None => vec![
+            format!("lib{name}.a"),
+            format!("lib{name}.rlib"),
+            format!("{name}.bc"),
+        ],
+    };
+    for dir in search_paths {
+        for file_name in &file_names {
+            let path = dir.join(file_name);
+            if path.is_file() {
+                debug!("found library {} at {:?}", name, path);
+                return Ok(path);
+            }
+        }
+    }
+
+    Err(LinkerError::LibraryNotFound(
+        name.to_owned(),
+        search_paths.to_vec(),
+    ))
+}
+
+// Java class files have the same magic as Mach-O universal binaries. Where universal binaries
+// have the number of architectures, class files have a version number which is always larger.
+fn is_macho_universal(data: &[u8]) -> bool {
+    data.len() >= 8
+        && matches!(&data[..4], b"\xca\xfe\xba\xbe" | b"\xca\xfe\xba\xbf")
+        && u32::from_be_bytes(data[4..8].try_into().unwrap()) < 43
+}
+
+// Returns the objects contained in a Mach-O universal binary, or None if `data` isn't a universal
+// binary.
+fn macho_universal_objects(data: &[u8]) -> Option<Vec<&[u8]>> {
+    if !is_macho_universal(data) {
+        return None;
+    }
+
+    let read = |offset: usize, width: usize| {
+        data.get(offset..offset + width)
+            .map(|bytes| bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize))
+    };
+    // fat_arch is {cputype, cpusubtype, offset, size, align}, fat_arch_64 has 64 bit offset and
+    // size and an extra reserved field
+    let (arch_size, width) = match data[3] {
+        0xbe => (20, 4),
+        _ => (32, 8),
+    };
+    let count = read(4, 4)?;
+    let mut objects = Vec::with_capacity(count);
+    for i in 0..count {
+        let arch = 8 + i * arch_size;
+        let offset = read(arch + 8, width)?;
+        let size = read(arch + 8 + width, width)?;
+        objects.push(data.get(offset..offset.checked_add(size)?)?);
+    }
+
+    Some(objects)
+}
+
 fn detect_input_type(data: &[u8]) -> Option<InputType> {
     if data.len() < 8 {
         return None;
@@ -517,13 +1833,262 @@ fn detect_input_type(data: &[u8]) -> Option<InputType> {
     match &data[..4] {
         b"\x42\x43\xC0\xDE" | b"\xDE\xC0\x17\x0b" => Some(Bitcode),
         b"\x7FELF" => Some(Elf),
-        b"\xcf\xfa\xed\xfe" => Some(MachO),
+        // 32 and 64 bit, big and little endian
+        b"\xfe\xed\xfa\xce" | b"\xce\xfa\xed\xfe" | b"\xfe\xed\xfa\xcf" | b"\xcf\xfa\xed\xfe" => {
+            Some(MachO)
+        }
+        // universal binaries, 32 and 64 bit
+        b"\xca\xfe\xba\xbe" | b"\xca\xfe\xba\xbf" if is_macho_universal(data) => Some(MachO),
         _ => {
             if &data[..8] == b"!<arch>\x0A" {
                 Some(Archive)
+            } else if is_llvm_ir(data) {
+                Some(LlvmIr)
             } else {
                 None
             }
         }
     }
 }
+
+// Textual IR doesn't have a magic number. It's recognized by its first line that isn't empty or
+// a comment, which must start a top level entity of a module, or by the `; ModuleID` comment
+// LLVM starts it with.
+fn is_llvm_ir(data: &[u8]) -> bool {
+    const ENTITIES: &[&[u8]] = &[
+        b"source_filename ",
+        b"target ",
+        b"define ",
+        b"declare ",
+        b"attributes ",
+        b"module asm ",
+    ];
+    // globals, named types, comdats and metadata, like `@foo = global i32 0`
+    const NAMED: &[u8] = b"@%$!";
+
+    for line in data.split(|b| *b == b'\n') {
+        let start = line
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(line.len());
+        let line = &line[start..];
+        if line.starts_with(b"; ModuleID") {
+            return true;
+        }
+        if line.is_empty() || line.starts_with(b";") {
+            continue;
+        }
+        return ENTITIES.iter().any(|entity| line.starts_with(entity))
+            || (NAMED.contains(&line[0]) && line.windows(3).any(|w| w == b" = "));
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, rc::Rc};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("bpf-linker-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_find_library() {
+        let dir = temp_dir("find-library");
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("bar.bc"), b"").unwrap();
+        fs::write(second.join("libfoo.a"), b"").unwrap();
+        fs::write(second.join("libbar.a"), b"").unwrap();
+        fs::write(second.join("baz.o"), b"").unwrap();
+        let search_paths = vec![first.clone(), second.clone()];
+
+        assert_eq!(
+            find_library("foo", &search_paths).unwrap(),
+            second.join("libfoo.a")
+        );
+        // directories are searched in order
+        assert_eq!(
+            find_library("bar", &search_paths).unwrap(),
+            first.join("bar.bc")
+        );
+        assert_eq!(
+            find_library(":baz.o", &search_paths).unwrap(),
+            second.join("baz.o")
+        );
+        match find_library("missing", &search_paths) {
+            Err(LinkerError::LibraryNotFound(name, searched)) => {
+                assert_eq!(name, "missing");
+                assert_eq!(searched, search_paths);
+            }
+            res => panic!("unexpected result {res:?}"),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_detect_macho() {
+        for magic in [
+            b"\xfe\xed\xfa\xce",
+            b"\xce\xfa\xed\xfe",
+            b"\xfe\xed\xfa\xcf",
+            b"\xcf\xfa\xed\xfe",
+        ] {
+            let data = [&magic[..], &[0; 4]].concat();
+            assert_eq!(detect_input_type(&data), Some(InputType::MachO));
+        }
+
+        let universal = b"\xca\xfe\xba\xbe\x00\x00\x00\x02";
+        assert_eq!(detect_input_type(universal), Some(InputType::MachO));
+        let universal_64 = b"\xca\xfe\xba\xbf\x00\x00\x00\x02";
+        assert_eq!(detect_input_type(universal_64), Some(InputType::MachO));
+        // java class file, version 52
+        let class = b"\xca\xfe\xba\xbe\x00\x00\x00\x34";
+        assert_eq!(detect_input_type(class), None);
+    }
+
+    #[test]
+    fn test_detect_llvm_ir() {
+        for ir in [
+            &b"; ModuleID = 'foo'\nsource_filename = \"foo\"\n"[..],
+            b"define i32 @fixture() {\n  ret i32 42\n}\n",
+            b"; a comment\n\n  target triple = \"bpfel\"\n",
+            b"@MAP = global [4 x i32] zeroinitializer\n",
+            b"%struct.T = type { i32 }\n",
+        ] {
+            assert_eq!(detect_input_type(ir), Some(InputType::LlvmIr));
+        }
+        for data in [
+            &b"%PDF-1.4 binary data"[..],
+            b"; only comments\n; nothing else\n",
+            b"int main() { return 0; }\n",
+        ] {
+            assert_eq!(detect_input_type(data), None);
+        }
+    }
+
+    #[test]
+    fn test_macho_universal_objects() {
+        let mut data = b"\xca\xfe\xba\xbe\x00\x00\x00\x02".to_vec();
+        for (offset, size) in [(48u32, 5u32), (53, 6)] {
+            for field in [7, 3, offset, size, 0] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+        }
+        data.extend_from_slice(b"firstsecond");
+        assert_eq!(
+            macho_universal_objects(&data).unwrap(),
+            vec![&b"first"[..], &b"second"[..]]
+        );
+        // out of bounds
+        data.truncate(56);
+        assert!(macho_universal_objects(&data).is_none());
+    }
+
+    #[test]
+    fn test_multiply_defined_symbol() {
+        assert_eq!(
+            multiply_defined_symbol("Linking globals named 'foo': symbol multiply defined!"),
+            Some("foo")
+        );
+        assert_eq!(
+            multiply_defined_symbol("Linking COMDATs named 'foo': invalid selection kinds!"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let diagnostic = Diagnostic::new(
+            Severity::Error,
+            Phase::Codegen,
+            "src/main.rs:10:5: in function foo i32 (ptr): A call to built-in function 'memcpy' is not supported.",
+        );
+        assert_eq!(diagnostic.function.as_deref(), Some("foo"));
+        assert_eq!(
+            diagnostic.location,
+            Some(SourceLocation {
+                file: "src/main.rs".to_string(),
+                line: 10,
+                column: 5
+            })
+        );
+        assert_eq!(
+            diagnostic.message,
+            "A call to built-in function 'memcpy' is not supported."
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "error: src/main.rs:10:5: in function foo: A call to built-in function 'memcpy' is not supported."
+        );
+
+        let diagnostic = Diagnostic::new(
+            Severity::Error,
+            Phase::Codegen,
+            "<unknown>:0:0: in function bar void (): too many arguments",
+        );
+        assert_eq!(diagnostic.function.as_deref(), Some("bar"));
+        assert_eq!(diagnostic.location, None);
+        assert_eq!(diagnostic.message, "too many arguments");
+
+        let diagnostic = Diagnostic::new(
+            Severity::Warning,
+            Phase::Link,
+            "Linking two modules of different target triples",
+        );
+        assert_eq!(diagnostic.function, None);
+        assert_eq!(
+            diagnostic.message,
+            "Linking two modules of different target triples"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_handler() {
+        let handled = Rc::new(RefCell::new(Vec::new()));
+        let handler = Rc::clone(&handled);
+        let diagnostics = Diagnostics::new(Some(DiagnosticHandler(Box::new(move |diagnostic| {
+            handler.borrow_mut().push(diagnostic.clone())
+        }))));
+        diagnostics.report(Severity::Warning, "dropped by start()");
+        diagnostics.start(Phase::Link);
+        diagnostics.report(Severity::Error, "symbol multiply defined!");
+
+        // the handler gets every diagnostic, take() only the ones of the current phase
+        let handled = handled.borrow();
+        assert_eq!(handled.len(), 2);
+        assert_eq!(handled[0].phase, Phase::Parse);
+        assert_eq!(handled[1].phase, Phase::Link);
+        assert_eq!(diagnostics.take(), handled[1..]);
+        assert!(diagnostics.take().is_empty());
+    }
+
+    #[test]
+    fn test_fatal() {
+        let diagnostics = Diagnostics::new(None);
+        diagnostics.start(Phase::Link);
+        let input = diagnostics.set_input(Path::new("foo.o"));
+        assert_eq!(
+            diagnostics.fatal("out of registers"),
+            "LLVM fatal error during link of foo.o: out of registers"
+        );
+        let reported = diagnostics.take();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].phase, Phase::Link);
+
+        // the input is reset when the guard is dropped
+        drop(input);
+        assert_eq!(
+            diagnostics.fatal("out of registers"),
+            "LLVM fatal error during link: out of registers"
+        );
+    }
+}
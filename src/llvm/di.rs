@@ -0,0 +1,349 @@
+use std::{collections::HashSet, ffi::CStr, slice};
+
+use llvm_sys::core::*;
+use llvm_sys::debuginfo::*;
+use llvm_sys::prelude::*;
+
+use crate::DebugInfo;
+
+use super::{
+    dbg_kind, has_local_linkage,
+    iter::{IterBasicBlocks, IterInstructions, IterModuleFunctions, IterModuleGlobals},
+    symbol_name,
+};
+
+// The C API doesn't have accessors for most of the DI* node fields, so they're read as MDNode
+// operands. The indices follow the operand layout of the classes in llvm/IR/DebugInfoMetadata.h.
+const DERIVED_TYPE_BASE_TYPE: usize = 3;
+const DERIVED_TYPE_EXTRA_DATA: usize = 4;
+const COMPOSITE_TYPE_BASE_TYPE: usize = 3;
+const COMPOSITE_TYPE_ELEMENTS: usize = 4;
+const COMPOSITE_TYPE_DISCRIMINATOR: usize = 8;
+const SUBROUTINE_TYPE_TYPES: usize = 3;
+const SUBPROGRAM_TYPE: usize = 4;
+const SUBPROGRAM_RETAINED_NODES: usize = 7;
+const VARIABLE_TYPE: usize = 3;
+
+/// A type in the debug info that LLVM's BTF generation can't handle.
+pub struct UnsupportedType {
+    /// The function or global that references the type.
+    pub symbol: String,
+    pub type_name: String,
+    pub reason: String,
+}
+
+/// Returns the operands of the metadata node `md`. Missing operands are null.
+pub unsafe fn operands(context: LLVMContextRef, md: LLVMMetadataRef) -> Vec<LLVMMetadataRef> {
+    let value = LLVMMetadataAsValue(context, md);
+    let mut operands = vec![std::ptr::null_mut(); LLVMGetMDNodeNumOperands(value) as usize];
+    LLVMGetMDNodeOperands(value, operands.as_mut_ptr());
+    operands
+        .into_iter()
+        .map(|op| {
+            if op.is_null() {
+                std::ptr::null_mut()
+            } else {
+                LLVMValueAsMetadata(op)
+            }
+        })
+        .collect()
+}
+
+unsafe fn operand(context: LLVMContextRef, md: LLVMMetadataRef, index: usize) -> LLVMMetadataRef {
+    operands(context, md)
+        .get(index)
+        .copied()
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// Returns the name of the DIType `md`.
+pub unsafe fn type_name(md: LLVMMetadataRef) -> String {
+    let mut len = 0;
+    let ptr = LLVMDITypeGetName(md, &mut len);
+    if ptr.is_null() || len == 0 {
+        return "<unnamed>".to_owned();
+    }
+    String::from_utf8_lossy(slice::from_raw_parts(ptr as *const u8, len)).into_owned()
+}
+
+/// Returns the DIGlobalVariableExpressions attached to `global`.
+pub unsafe fn global_variable_expressions(
+    context: LLVMContextRef,
+    global: LLVMValueRef,
+) -> Vec<LLVMMetadataRef> {
+    let dbg_kind = dbg_kind(context);
+    let mut count = 0;
+    let entries = LLVMGlobalCopyAllMetadata(global, &mut count);
+    let expressions = (0..count as u32)
+        .filter(|&i| LLVMValueMetadataEntriesGetKind(entries, i) == dbg_kind)
+        .map(|i| LLVMValueMetadataEntriesGetMetadata(entries, i))
+        .collect();
+    if !entries.is_null() {
+        LLVMDisposeValueMetadataEntries(entries);
+    }
+
+    expressions
+}
+
+/// Returns the root types BTF is generated from for `function`: the types in its signature and
+/// the types of its local variables.
+unsafe fn function_types(context: LLVMContextRef, function: LLVMValueRef) -> Vec<LLVMMetadataRef> {
+    let subprogram = LLVMGetSubprogram(function);
+    if subprogram.is_null() {
+        return Vec::new();
+    }
+
+    let operands = operands(context, subprogram);
+    let mut types = operands
+        .get(SUBPROGRAM_TYPE)
+        .copied()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let mut variables = match operands.get(SUBPROGRAM_RETAINED_NODES) {
+        Some(nodes) if !nodes.is_null() => self::operands(context, *nodes),
+        _ => Vec::new(),
+    };
+    for block in function.basic_blocks_iter() {
+        for instruction in block.instructions_iter() {
+            if let Some(variable) = debug_intrinsic_variable(instruction) {
+                variables.push(variable);
+            }
+        }
+    }
+    types.extend(
+        variables
+            .into_iter()
+            .filter(|v| {
+                matches!(
+                    LLVMGetMetadataKind(*v),
+                    LLVMMetadataKind::LLVMDILocalVariableMetadataKind
+                )
+            })
+            .map(|v| operand(context, v, VARIABLE_TYPE)),
+    );
+
+    types
+}
+
+// returns the name of the function called by `instruction`, if it's a direct call
+unsafe fn called_function_name(instruction: LLVMValueRef) -> Option<&'static [u8]> {
+    if LLVMIsACallInst(instruction).is_null() {
+        return None;
+    }
+    let callee = LLVMGetCalledValue(instruction);
+    if callee.is_null() || LLVMIsAFunction(callee).is_null() {
+        return None;
+    }
+    let mut len = 0;
+    let name = LLVMGetValueName2(callee, &mut len) as *const u8;
+    Some(slice::from_raw_parts(name, len))
+}
+
+// returns the DILocalVariable of calls to llvm.dbg.declare and llvm.dbg.value
+unsafe fn debug_intrinsic_variable(instruction: LLVMValueRef) -> Option<LLVMMetadataRef> {
+    if !matches!(
+        called_function_name(instruction),
+        Some(b"llvm.dbg.declare" | b"llvm.dbg.value")
+    ) {
+        return None;
+    }
+
+    Some(LLVMValueAsMetadata(LLVMGetOperand(instruction, 1)))
+}
+
+/// Removes the debug info that isn't needed for `level` from `module`. Only the debug info of
+/// globals in the `.maps` section is kept, and depending on `level` the signatures and line info
+/// of functions. `DebugInfo::Signatures` only keeps the functions that are exported, which are
+/// the ones left with external linkage after optimization. `DebugInfo::LineTablesOnly` doesn't
+/// keep globals.
+pub unsafe fn select_debug_info(module: LLVMModuleRef, level: DebugInfo) {
+    let context = LLVMGetModuleContext(module);
+    let dbg_kind = dbg_kind(context);
+
+    for global in module.globals_iter() {
+        let section = LLVMGetSection(global);
+        if level == DebugInfo::LineTablesOnly
+            || section.is_null()
+            || CStr::from_ptr(section).to_bytes() != b".maps"
+        {
+            LLVMGlobalEraseMetadata(global, dbg_kind);
+        }
+    }
+
+    for function in module.functions_iter() {
+        let keep_function = match level {
+            DebugInfo::Maps => false,
+            DebugInfo::Signatures => !has_local_linkage(function),
+            _ => true,
+        };
+        let subprogram = if keep_function {
+            LLVMGetSubprogram(function)
+        } else {
+            LLVMGlobalEraseMetadata(function, dbg_kind);
+            std::ptr::null_mut()
+        };
+        let keep_locations = matches!(level, DebugInfo::Lines | DebugInfo::LineTablesOnly);
+        strip_function_debug_info(context, function, subprogram, keep_locations);
+    }
+}
+
+/// Removes the line info of the functions in `module`.
+pub unsafe fn strip_line_info(module: LLVMModuleRef) {
+    let context = LLVMGetModuleContext(module);
+    for function in module.functions_iter() {
+        strip_function_debug_info(context, function, LLVMGetSubprogram(function), false);
+    }
+}
+
+// removes the calls to debug intrinsics in `function` and, unless `keep_locations` is set, the
+// locations of its instructions
+unsafe fn strip_function_debug_info(
+    context: LLVMContextRef,
+    function: LLVMValueRef,
+    subprogram: LLVMMetadataRef,
+    keep_locations: bool,
+) {
+    let mut intrinsics = Vec::new();
+    for block in function.basic_blocks_iter() {
+        for instruction in block.instructions_iter() {
+            // local variables are never needed for BTF
+            if matches!(called_function_name(instruction), Some(name) if name.starts_with(b"llvm.dbg."))
+            {
+                intrinsics.push(instruction);
+                continue;
+            }
+            if keep_locations && !subprogram.is_null() {
+                continue;
+            }
+            // The verifier requires calls in functions with debug info to have a location, give
+            // them one on line 0, which BTF generation skips.
+            let location = if !subprogram.is_null() && !LLVMIsACallInst(instruction).is_null() {
+                LLVMDIBuilderCreateDebugLocation(context, 0, 0, subprogram, std::ptr::null_mut())
+            } else {
+                std::ptr::null_mut()
+            };
+            LLVMInstructionSetDebugLoc(instruction, location);
+        }
+    }
+    for instruction in intrinsics {
+        LLVMInstructionEraseFromParent(instruction);
+    }
+}
+
+/// Looks for debug info types that crash LLVM's BTF generation, in the types referenced by the
+/// functions and globals in `module`.
+pub unsafe fn find_unsupported_btf_type(
+    context: LLVMContextRef,
+    module: LLVMModuleRef,
+) -> Option<UnsupportedType> {
+    let mut visited = HashSet::new();
+    for function in module.functions_iter() {
+        for ty in function_types(context, function) {
+            if let Some((type_name, reason)) = check_btf_type(context, ty, &mut visited) {
+                return Some(UnsupportedType {
+                    symbol: symbol_name(function),
+                    type_name,
+                    reason,
+                });
+            }
+        }
+    }
+    for global in module.globals_iter() {
+        for expression in global_variable_expressions(context, global) {
+            let variable = LLVMDIGlobalVariableExpressionGetVariable(expression);
+            let ty = operand(context, variable, VARIABLE_TYPE);
+            if let Some((type_name, reason)) = check_btf_type(context, ty, &mut visited) {
+                return Some(UnsupportedType {
+                    symbol: symbol_name(global),
+                    type_name,
+                    reason,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+// returns the elements of the composite type `ty`, without the null ones
+unsafe fn composite_elements(context: LLVMContextRef, ty: LLVMMetadataRef) -> Vec<LLVMMetadataRef> {
+    let elements = operand(context, ty, COMPOSITE_TYPE_ELEMENTS);
+    if elements.is_null() {
+        return Vec::new();
+    }
+    operands(context, elements)
+        .into_iter()
+        .filter(|element| !element.is_null())
+        .collect()
+}
+
+// whether the composite type `ty` is a DW_TAG_variant_part. The C API can't read tags, but
+// variant parts have a discriminator, or when there's no tag, members carrying the discriminant
+// value of their variant as extra data.
+unsafe fn is_variant_part(context: LLVMContextRef, ty: LLVMMetadataRef) -> bool {
+    if !operand(context, ty, COMPOSITE_TYPE_DISCRIMINATOR).is_null() {
+        return true;
+    }
+    composite_elements(context, ty).into_iter().any(|element| {
+        matches!(
+            LLVMGetMetadataKind(element),
+            LLVMMetadataKind::LLVMDIDerivedTypeMetadataKind
+        ) && !operand(context, element, DERIVED_TYPE_EXTRA_DATA).is_null()
+    })
+}
+
+// returns the name of the first type reachable from `ty` that BTF can't handle, and why
+unsafe fn check_btf_type(
+    context: LLVMContextRef,
+    ty: LLVMMetadataRef,
+    visited: &mut HashSet<LLVMMetadataRef>,
+) -> Option<(String, String)> {
+    if ty.is_null() || !visited.insert(ty) {
+        return None;
+    }
+
+    use LLVMMetadataKind::*;
+    match LLVMGetMetadataKind(ty) {
+        LLVMDIDerivedTypeMetadataKind => check_btf_type(
+            context,
+            operand(context, ty, DERIVED_TYPE_BASE_TYPE),
+            visited,
+        ),
+        LLVMDISubroutineTypeMetadataKind => {
+            let types = operand(context, ty, SUBROUTINE_TYPE_TYPES);
+            if types.is_null() {
+                return None;
+            }
+            operands(context, types)
+                .into_iter()
+                .find_map(|ty| check_btf_type(context, ty, visited))
+        }
+        LLVMDICompositeTypeMetadataKind => {
+            for element in composite_elements(context, ty) {
+                match LLVMGetMetadataKind(element) {
+                    LLVMDIDerivedTypeMetadataKind => {
+                        if let Some(unsupported) = check_btf_type(context, element, visited) {
+                            return Some(unsupported);
+                        }
+                    }
+                    LLVMDICompositeTypeMetadataKind if is_variant_part(context, element) => {
+                        return Some((
+                            type_name(ty),
+                            "it contains a variant part, which rustc emits for enums with data \
+                             variants"
+                                .to_owned(),
+                        ))
+                    }
+                    // enumerators, subranges, methods and nested type declarations are fine
+                    _ => {}
+                }
+            }
+            check_btf_type(
+                context,
+                operand(context, ty, COMPOSITE_TYPE_BASE_TYPE),
+                visited,
+            )
+        }
+        _ => None,
+    }
+}
@@ -68,3 +68,23 @@ llvm_iterator!(
     LLVMGetFirstGlobalAlias,
     LLVMGetNextGlobalAlias
 );
+
+llvm_iterator!(
+    IterBasicBlocks,
+    BasicBlocksIter,
+    LLVMValueRef,
+    basic_blocks_iter,
+    LLVMBasicBlockRef,
+    LLVMGetFirstBasicBlock,
+    LLVMGetNextBasicBlock
+);
+
+llvm_iterator!(
+    IterInstructions,
+    InstructionsIter,
+    LLVMBasicBlockRef,
+    instructions_iter,
+    LLVMValueRef,
+    LLVMGetFirstInstruction,
+    LLVMGetNextInstruction
+);
@@ -1,17 +1,20 @@
+mod di;
 mod iter;
 mod message;
 
 use std::{
-    collections::HashSet,
+    cell::Cell,
+    collections::{HashMap, HashSet},
     ffi::{c_void, CStr, CString},
     os::raw::c_char,
-    ptr, slice,
+    process, ptr, slice,
 };
 
 use libc::c_char as libc_char;
 use llvm_sys::bit_reader::*;
 use llvm_sys::core::*;
-use llvm_sys::debuginfo::LLVMStripModuleDebugInfo;
+use llvm_sys::debuginfo::{LLVMGetSubprogram, LLVMStripModuleDebugInfo};
+use llvm_sys::ir_reader::LLVMParseIRInContext;
 use llvm_sys::linker::LLVMLinkModules2;
 use llvm_sys::object::*;
 use llvm_sys::prelude::*;
@@ -24,9 +27,15 @@ use llvm_sys::LLVMAttributeFunctionIndex;
 use llvm_sys::{LLVMLinkage, LLVMVisibility};
 use log::*;
 
+pub use self::di::{find_unsupported_btf_type, strip_line_info};
 use self::message::Message;
-use crate::OptLevel;
-use iter::{IterModuleFunctions, IterModuleGlobalAliases, IterModuleGlobals};
+use crate::{
+    export::ExportSymbols, linker::Diagnostics, DebugInfo, OptLevel, Severity, LLVM_FATAL_EXIT_CODE,
+};
+use iter::{
+    IterBasicBlocks, IterInstructions, IterModuleFunctions, IterModuleGlobalAliases,
+    IterModuleGlobals,
+};
 
 pub unsafe fn init<T: AsRef<str>>(args: &[T], overview: &str) {
     LLVMInitializeBPFTarget();
@@ -59,10 +68,17 @@ pub unsafe fn create_module(name: &str, context: LLVMContextRef) -> Option<LLVMM
     Some(module)
 }
 
+/// Names of the sections bitcode gets embedded in, by priority. `.llvmbc` in ELF objects,
+/// `__LLVM,__bitcode` in Mach-O objects and `.llvm.lto` in ELF objects built with
+/// `-ffat-lto-objects`.
+const BITCODE_SECTIONS: &[&str] = &[".llvmbc", "__bitcode", ".llvm.lto"];
+
+/// Returns the name and the contents of the section containing the bitcode embedded in the object
+/// file `data`. The contents are returned as they are, they might be compressed.
 pub unsafe fn find_embedded_bitcode(
     _context: LLVMContextRef,
     data: &[u8],
-) -> Result<Option<Vec<u8>>, String> {
+) -> Result<Option<(String, Vec<u8>)>, String> {
     let buffer_name = CString::new("mem_buffer").unwrap();
     let buffer = LLVMCreateMemoryBufferWithMemoryRange(
         data.as_ptr() as *const libc_char,
@@ -77,17 +93,37 @@ pub unsafe fn find_embedded_bitcode(
         return Err(message.to_string());
     }
 
-    let mut ret = None;
+    use LLVMBinaryType::*;
+    match LLVMBinaryGetType(bin) {
+        LLVMBinaryTypeELF32L
+        | LLVMBinaryTypeELF32B
+        | LLVMBinaryTypeELF64L
+        | LLVMBinaryTypeELF64B
+        | LLVMBinaryTypeMachO32L
+        | LLVMBinaryTypeMachO32B
+        | LLVMBinaryTypeMachO64L
+        | LLVMBinaryTypeMachO64B => {}
+        ty => {
+            LLVMDisposeBinary(bin);
+            LLVMDisposeMemoryBuffer(buffer);
+            return Err(format!("unsupported binary type {ty:?}"));
+        }
+    }
+
+    // (priority, name, contents)
+    let mut ret: Option<(usize, String, Vec<u8>)> = None;
     let iter = LLVMObjectFileCopySectionIterator(bin);
     while LLVMObjectFileIsSectionIteratorAtEnd(bin, iter) == 0 {
         let name = LLVMGetSectionName(iter);
         if !name.is_null() {
-            let name = CStr::from_ptr(name);
-            if name.to_str().unwrap() == ".llvmbc" {
-                let buf = LLVMGetSectionContents(iter) as *const u8;
-                let size = LLVMGetSectionSize(iter) as usize;
-                ret = Some(slice::from_raw_parts(buf, size).to_vec());
-                break;
+            let name = CStr::from_ptr(name).to_str().unwrap();
+            if let Some(priority) = BITCODE_SECTIONS.iter().position(|s| *s == name) {
+                if !matches!(&ret, Some((p, _, _)) if *p <= priority) {
+                    let buf = LLVMGetSectionContents(iter) as *const u8;
+                    let size = LLVMGetSectionSize(iter) as usize;
+                    let contents = slice::from_raw_parts(buf, size).to_vec();
+                    ret = Some((priority, name.to_owned(), contents));
+                }
             }
         }
         LLVMMoveToNextSection(iter);
@@ -96,16 +132,123 @@ pub unsafe fn find_embedded_bitcode(
     LLVMDisposeBinary(bin);
     LLVMDisposeMemoryBuffer(buffer);
 
-    Ok(ret)
+    Ok(ret.map(|(_, name, contents)| (name, contents)))
 }
 
-#[must_use]
-pub unsafe fn link_bitcode_buffer(
+/// Parses the bitcode in `buffer` into a new module.
+pub unsafe fn parse_bitcode(context: LLVMContextRef, buffer: &[u8]) -> Option<LLVMModuleRef> {
+    let buffer_name = CString::new("mem_buffer").unwrap();
+    let buffer = LLVMCreateMemoryBufferWithMemoryRange(
+        buffer.as_ptr() as *const libc_char,
+        buffer.len(),
+        buffer_name.as_ptr(),
+        0,
+    );
+
+    let mut module = ptr::null_mut();
+    let parsed = LLVMParseBitcodeInContext2(context, buffer, &mut module) == 0;
+    LLVMDisposeMemoryBuffer(buffer);
+
+    parsed.then_some(module)
+}
+
+/// Parses the textual IR in `buffer` into a new module.
+///
+/// Returns an error including the line and column if parsing fails.
+pub unsafe fn parse_ir(
+    context: LLVMContextRef,
+    name: &str,
+    buffer: &[u8],
+) -> Result<LLVMModuleRef, String> {
+    let buffer_name = CString::new(name).unwrap();
+    // the IR parser needs a NUL terminated buffer, which the copy provides
+    let buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(
+        buffer.as_ptr() as *const libc_char,
+        buffer.len(),
+        buffer_name.as_ptr(),
+    );
+
+    let mut module = ptr::null_mut();
+    let mut message = Message::new();
+    // the parser takes ownership of the buffer
+    if LLVMParseIRInContext(context, buffer, &mut module, message.as_mut_ptr()) != 0 {
+        return Err(message.to_string().trim_end().to_owned());
+    }
+
+    Ok(module)
+}
+
+/// Prints `module` as textual IR, transforms the IR with `rewrite` and parses the result into a
+/// new module. `module` is left untouched.
+///
+/// Fails if the new module has lost debug info: when the rewritten debug info doesn't verify,
+/// LLVM drops all of it with nothing more than a warning.
+pub unsafe fn rewrite_ir(
     context: LLVMContextRef,
     module: LLVMModuleRef,
+    rewrite: impl FnOnce(&str) -> String,
+) -> Result<LLVMModuleRef, String> {
+    let ir = Message {
+        ptr: LLVMPrintModuleToString(module),
+    };
+    let ir = rewrite(&CStr::from_ptr(ir.ptr).to_string_lossy());
+
+    let mut len = 0;
+    let name = LLVMGetModuleIdentifier(module, &mut len);
+    let name = String::from_utf8_lossy(slice::from_raw_parts(name as *const u8, len));
+
+    let rewritten = parse_ir(context, &name, ir.as_bytes())?;
+    let (units, subprograms) = debug_info_counts(module);
+    let (rewritten_units, rewritten_subprograms) = debug_info_counts(rewritten);
+    if rewritten_units < units || rewritten_subprograms < subprograms {
+        LLVMDisposeModule(rewritten);
+        return Err("LLVM dropped the rewritten debug info as invalid".to_owned());
+    }
+
+    Ok(rewritten)
+}
+
+// returns the number of compile units and of functions with a subprogram in `module`
+unsafe fn debug_info_counts(module: LLVMModuleRef) -> (u32, usize) {
+    let cu = CString::new("llvm.dbg.cu").unwrap();
+    let units = LLVMGetNamedMetadataNumOperands(module, cu.as_ptr());
+    let subprograms = module
+        .functions_iter()
+        .filter(|&function| !LLVMGetSubprogram(function).is_null())
+        .count();
+    (units, subprograms)
+}
+
+/// Links `src` into `module`. `src` is destroyed.
+#[must_use]
+pub unsafe fn link_module(module: LLVMModuleRef, src: LLVMModuleRef) -> bool {
+    LLVMLinkModules2(module, src) == 0
+}
+
+/// Returns the names of the symbols with external linkage that `module` defines.
+pub unsafe fn defined_symbols(module: LLVMModuleRef) -> HashSet<String> {
+    module_symbols(module)
+        .filter(|&sym| LLVMIsDeclaration(sym) == 0 && !has_local_linkage(sym))
+        .map(symbol_name)
+        .collect()
+}
+
+/// Returns the symbols with external linkage defined by `module` and their sections.
+pub unsafe fn defined_symbol_sections(module: LLVMModuleRef) -> HashMap<String, Option<String>> {
+    module_symbols(module)
+        .filter(|&sym| LLVMIsDeclaration(sym) == 0 && !has_local_linkage(sym))
+        .map(|sym| (symbol_name(sym), section_name(sym)))
+        .collect()
+}
+
+/// Returns the symbols with external linkage defined by the bitcode in `buffer` and their
+/// sections.
+///
+/// The module is loaded lazily, so function bodies are not parsed.
+pub unsafe fn bitcode_defined_symbols(
+    context: LLVMContextRef,
     buffer: &[u8],
-) -> bool {
-    let mut linked = false;
+) -> Option<HashMap<String, Option<String>>> {
     let buffer_name = CString::new("mem_buffer").unwrap();
     let buffer = LLVMCreateMemoryBufferWithMemoryRange(
         buffer.as_ptr() as *const libc_char,
@@ -114,15 +257,279 @@ pub unsafe fn link_bitcode_buffer(
         0,
     );
 
-    let mut temp_module = ptr::null_mut();
+    let mut module = ptr::null_mut();
+    // on success the module takes ownership of the buffer
+    if LLVMGetBitcodeModuleInContext2(context, buffer, &mut module) != 0 {
+        LLVMDisposeMemoryBuffer(buffer);
+        return None;
+    }
+    let symbols = defined_symbol_sections(module);
+    LLVMDisposeModule(module);
+
+    Some(symbols)
+}
 
-    if LLVMParseBitcodeInContext2(context, buffer, &mut temp_module) == 0 {
-        linked = LLVMLinkModules2(module, temp_module) == 0;
+/// Returns the symbols with external linkage defined by the textual IR in `buffer` and their
+/// sections.
+///
+/// Returns an error including the line and column if parsing fails.
+pub unsafe fn ir_defined_symbols(
+    context: LLVMContextRef,
+    name: &str,
+    buffer: &[u8],
+) -> Result<HashMap<String, Option<String>>, String> {
+    let module = parse_ir(context, name, buffer)?;
+    let symbols = defined_symbol_sections(module);
+    LLVMDisposeModule(module);
+
+    Ok(symbols)
+}
+
+/// Returns the names of the symbols in `module` and whether each is defined or only declared.
+pub unsafe fn symbol_definitions(module: LLVMModuleRef) -> Vec<(String, bool)> {
+    module_symbols(module)
+        .map(|sym| (symbol_name(sym), LLVMIsDeclaration(sym) == 0))
+        .filter(|(name, _)| !name.starts_with("llvm."))
+        .collect()
+}
+
+/// A function, global or alias of a module.
+pub struct Symbol {
+    pub name: String,
+    /// Whether the module defines the symbol or only declares it.
+    pub defined: bool,
+    /// Whether the symbol has internal or private linkage.
+    pub local: bool,
+}
+
+/// Returns the named functions, globals and aliases of `module`, intrinsics excluded.
+pub unsafe fn symbols(module: LLVMModuleRef) -> Vec<Symbol> {
+    module_symbols(module)
+        .map(|sym| Symbol {
+            name: symbol_name(sym),
+            defined: LLVMIsDeclaration(sym) == 0,
+            local: has_local_linkage(sym),
+        })
+        .filter(|symbol| !symbol.name.is_empty() && !symbol.name.starts_with("llvm."))
+        .collect()
+}
+
+/// Renames the symbols with local linkage of `module` whose name is `taken` to `<name>.<n>`, with
+/// the first `n` that isn't taken or used by `module`. LLVM renames them the same way when linking
+/// `module`, doing it first lets the caller know their final names.
+pub unsafe fn rename_local_symbols(module: LLVMModuleRef, taken: impl Fn(&str) -> bool) {
+    for sym in module_symbols(module) {
+        let name = symbol_name(sym);
+        if name.is_empty() || !has_local_linkage(sym) || !taken(&name) {
+            continue;
+        }
+        let new_name = (1..)
+            .map(|n| format!("{name}.{n}"))
+            .find(|new_name| !taken(new_name) && !has_symbol(module, new_name))
+            .unwrap();
+        LLVMSetValueName2(sym, new_name.as_ptr() as *const c_char, new_name.len());
     }
+}
 
-    LLVMDisposeMemoryBuffer(buffer);
+/// Renames the symbol with local linkage of `module` named `name` to `<name>.<n>`, with the first
+/// `n` that isn't used by `module` or `src`. When `src` has a symbol with external linkage named
+/// `name`, LLVM renames the local symbol of `module` the same way when linking `src` into it.
+/// Returns the new name, or `None` if `module` has no local symbol named `name`.
+pub unsafe fn rename_local_symbol(
+    module: LLVMModuleRef,
+    src: LLVMModuleRef,
+    name: &str,
+) -> Option<String> {
+    let sym = named_symbol(module, name).filter(|&sym| has_local_linkage(sym))?;
+    let new_name = (1..)
+        .map(|n| format!("{name}.{n}"))
+        .find(|new_name| !has_symbol(module, new_name) && !has_symbol(src, new_name))
+        .unwrap();
+    LLVMSetValueName2(sym, new_name.as_ptr() as *const c_char, new_name.len());
+
+    Some(new_name)
+}
 
-    linked
+// returns the function, global or alias of `module` named `name`
+unsafe fn named_symbol(module: LLVMModuleRef, name: &str) -> Option<LLVMValueRef> {
+    let c_name = CString::new(name).unwrap();
+    [
+        LLVMGetNamedFunction(module, c_name.as_ptr()),
+        LLVMGetNamedGlobal(module, c_name.as_ptr()),
+        LLVMGetNamedGlobalAlias(module, c_name.as_ptr(), name.len()),
+    ]
+    .into_iter()
+    .find(|sym| !sym.is_null())
+}
+
+// returns whether `module` has a function, global or alias named `name`
+unsafe fn has_symbol(module: LLVMModuleRef, name: &str) -> bool {
+    named_symbol(module, name).is_some()
+}
+
+/// Returns the symbols each function, global and alias defined in `module` refers to, directly
+/// or through constants.
+pub unsafe fn symbol_references(module: LLVMModuleRef) -> HashMap<String, Vec<String>> {
+    let mut references = HashMap::new();
+    for sym in module_symbols(module) {
+        if LLVMIsDeclaration(sym) != 0 {
+            continue;
+        }
+        let mut operands = Vec::new();
+        if !LLVMIsAFunction(sym).is_null() {
+            for block in sym.basic_blocks_iter() {
+                for instruction in block.instructions_iter() {
+                    operands.extend(
+                        (0..LLVMGetNumOperands(instruction) as u32)
+                            .map(|i| LLVMGetOperand(instruction, i)),
+                    );
+                }
+            }
+        } else if !LLVMIsAGlobalAlias(sym).is_null() {
+            operands.push(LLVMAliasGetAliasee(sym));
+        } else {
+            operands.push(LLVMGetInitializer(sym));
+        }
+
+        let mut referenced = Vec::new();
+        let mut visited = HashSet::new();
+        while let Some(value) = operands.pop() {
+            if value.is_null() || !visited.insert(value) {
+                continue;
+            }
+            if !LLVMIsAGlobalValue(value).is_null() {
+                let name = symbol_name(value);
+                if !referenced.contains(&name) {
+                    referenced.push(name);
+                }
+            } else if !LLVMIsAConstant(value).is_null() {
+                // constant expressions, arrays and structs can refer to globals
+                operands.extend(
+                    (0..LLVMGetNumOperands(value).max(0) as u32).map(|i| LLVMGetOperand(value, i)),
+                );
+            }
+        }
+        references.insert(symbol_name(sym), referenced);
+    }
+
+    references
+}
+
+/// A call made by a function.
+pub enum Call {
+    /// A call to the named function.
+    Direct(String),
+    /// A call through a function pointer, described by its source location, or its IR when
+    /// there's no debug info.
+    Indirect(String),
+}
+
+/// Returns the calls made by every function defined in `module`. Calls to intrinsics, inline
+/// assembly and constant addresses, which is how BPF helpers are called, aren't included.
+pub unsafe fn calls(module: LLVMModuleRef) -> HashMap<String, Vec<Call>> {
+    let mut calls = HashMap::new();
+    for function in module.functions_iter() {
+        if LLVMIsDeclaration(function) != 0 {
+            continue;
+        }
+        let mut function_calls = Vec::new();
+        for block in function.basic_blocks_iter() {
+            for instruction in block.instructions_iter() {
+                if LLVMIsACallInst(instruction).is_null()
+                    && LLVMIsAInvokeInst(instruction).is_null()
+                {
+                    continue;
+                }
+                let mut callee = LLVMGetCalledValue(instruction);
+                while !LLVMIsAGlobalAlias(callee).is_null() {
+                    callee = LLVMAliasGetAliasee(callee);
+                }
+                if !LLVMIsAFunction(callee).is_null() {
+                    let name = symbol_name(callee);
+                    if !name.starts_with("llvm.") {
+                        function_calls.push(Call::Direct(name));
+                    }
+                } else if LLVMIsAInlineAsm(callee).is_null() && LLVMIsAConstant(callee).is_null() {
+                    function_calls.push(Call::Indirect(call_site(instruction)));
+                }
+            }
+        }
+        calls.insert(symbol_name(function), function_calls);
+    }
+
+    calls
+}
+
+// returns the source location of `instruction`, or its IR when it has none
+unsafe fn call_site(instruction: LLVMValueRef) -> String {
+    let mut len = 0;
+    let file = LLVMGetDebugLocFilename(instruction, &mut len);
+    if !file.is_null() && len > 0 {
+        let file = slice::from_raw_parts(file as *const u8, len as usize);
+        return format!(
+            "{}:{}:{}",
+            String::from_utf8_lossy(file),
+            LLVMGetDebugLocLine(instruction),
+            LLVMGetDebugLocColumn(instruction)
+        );
+    }
+    let ir = Message {
+        ptr: LLVMPrintValueToString(instruction),
+    };
+    format!("`{}`", ir.to_string().trim())
+}
+
+/// A function, global or alias defined by a module.
+pub struct Definition {
+    pub name: String,
+    /// `function`, `global` or `alias`.
+    pub kind: &'static str,
+    pub section: Option<String>,
+}
+
+/// Returns the functions, globals and aliases defined by `module`.
+pub unsafe fn definitions(module: LLVMModuleRef) -> Vec<Definition> {
+    module_symbols(module)
+        .filter(|&sym| LLVMIsDeclaration(sym) == 0)
+        .map(|sym| Definition {
+            name: symbol_name(sym),
+            kind: if !LLVMIsAFunction(sym).is_null() {
+                "function"
+            } else if !LLVMIsAGlobalAlias(sym).is_null() {
+                "alias"
+            } else {
+                "global"
+            },
+            section: section_name(sym),
+        })
+        .filter(|definition| !definition.name.starts_with("llvm."))
+        .collect()
+}
+
+// returns the section `value` is placed in, if it has one
+unsafe fn section_name(value: LLVMValueRef) -> Option<String> {
+    let section = LLVMGetSection(value);
+    if section.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(section).to_string_lossy().into_owned()).filter(|s| !s.is_empty())
+}
+
+fn module_symbols(module: LLVMModuleRef) -> impl Iterator<Item = LLVMValueRef> {
+    module
+        .functions_iter()
+        .chain(module.globals_iter())
+        .chain(module.global_aliases_iter())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+unsafe fn has_local_linkage(value: LLVMValueRef) -> bool {
+    use LLVMLinkage::*;
+    matches!(
+        LLVMGetLinkage(value),
+        LLVMInternalLinkage | LLVMPrivateLinkage
+    )
 }
 
 pub unsafe fn target_from_triple(triple: &CStr) -> Result<LLVMTargetRef, String> {
@@ -166,12 +573,24 @@ pub unsafe fn create_target_machine(
     }
 }
 
+/// Configures `module` for the target of `tm`, setting its triple and data layout.
+pub unsafe fn set_target(module: LLVMModuleRef, tm: LLVMTargetMachineRef) {
+    let triple = LLVMGetTargetMachineTriple(tm);
+    LLVMSetTarget(module, triple);
+    LLVMDisposeMessage(triple);
+    let layout = LLVMCreateTargetDataLayout(tm);
+    LLVMSetModuleDataLayout(module, layout);
+    LLVMDisposeTargetData(layout);
+}
+
 pub unsafe fn optimize(
     tm: LLVMTargetMachineRef,
     module: LLVMModuleRef,
     opt_level: OptLevel,
     ignore_inline_never: bool,
-    export_symbols: &HashSet<String>,
+    inline_all: bool,
+    export_symbols: &ExportSymbols,
+    debug_info: DebugInfo,
 ) {
     if module_asm_is_probestack(module) {
         LLVMSetModuleInlineAsm2(module, ptr::null_mut(), 0);
@@ -223,7 +642,13 @@ pub unsafe fn optimize(
     for function in module.functions_iter() {
         let name = symbol_name(function);
         if !name.starts_with("llvm.") {
-            if ignore_inline_never {
+            if inline_all && LLVMIsDeclaration(function) == 0 {
+                // BPF to BPF calls aren't supported, removing `noinline` isn't enough for the
+                // inliner to inline everything
+                remove_attribute(function, "noinline");
+                remove_attribute(function, "optnone");
+                add_attribute(function, "alwaysinline");
+            } else if ignore_inline_never {
                 remove_attribute(function, "noinline");
             }
             internalize(function, &name, export_symbols);
@@ -238,8 +663,14 @@ pub unsafe fn optimize(
     LLVMRunPassManager(mpm, module);
 
     // Some debug info generated by rustc seems to trigger a segfault in the
-    // BTF code in llvm, so strip it until that is fixed
-    LLVMStripModuleDebugInfo(module);
+    // BTF code in llvm, so only keep what was asked for
+    match debug_info {
+        DebugInfo::None => {
+            LLVMStripModuleDebugInfo(module);
+        }
+        DebugInfo::Full => {}
+        level => di::select_debug_info(module, level),
+    }
 }
 
 unsafe fn module_asm_is_probestack(module: LLVMModuleRef) -> bool {
@@ -253,6 +684,11 @@ unsafe fn module_asm_is_probestack(module: LLVMModuleRef) -> bool {
     asm.contains("__rust_probestack")
 }
 
+pub(crate) unsafe fn dbg_kind(context: LLVMContextRef) -> u32 {
+    let dbg = CString::new("dbg").unwrap();
+    LLVMGetMDKindIDInContext(context, dbg.as_ptr(), 3)
+}
+
 fn symbol_name(value: *mut llvm_sys::LLVMValue) -> String {
     let mut name_len = 0;
     unsafe { CStr::from_ptr(LLVMGetValueName2(value, &mut name_len)) }
@@ -266,6 +702,14 @@ unsafe fn remove_attribute(function: *mut llvm_sys::LLVMValue, name: &str) {
     LLVMRemoveEnumAttributeAtIndex(function, LLVMAttributeFunctionIndex, attr_kind);
 }
 
+unsafe fn add_attribute(function: *mut llvm_sys::LLVMValue, name: &str) {
+    let attr = CString::new(name).unwrap();
+    let attr_kind = LLVMGetEnumAttributeKindForName(attr.as_ptr(), name.len());
+    let context = LLVMGetModuleContext(LLVMGetGlobalParent(function));
+    let attr = LLVMCreateEnumAttribute(context, attr_kind, 0);
+    LLVMAddAttributeAtIndex(function, LLVMAttributeFunctionIndex, attr);
+}
+
 pub unsafe fn write_ir(module: LLVMModuleRef, output: &CStr) -> Result<(), String> {
     let mut message = Message::new();
     if LLVMPrintModuleToFile(module, output.as_ptr(), message.as_mut_ptr()) == 1 {
@@ -297,26 +741,79 @@ pub unsafe fn codegen(
     Ok(())
 }
 
-pub unsafe fn internalize(value: LLVMValueRef, name: &str, export_symbols: &HashSet<String>) {
-    if !name.starts_with("llvm.") && !export_symbols.contains(name) {
+pub unsafe fn internalize(value: LLVMValueRef, name: &str, export_symbols: &ExportSymbols) {
+    let section = section_name(value);
+    if !name.starts_with("llvm.") && !export_symbols.contains(name, section.as_deref()) {
         LLVMSetLinkage(value, LLVMLinkage::LLVMInternalLinkage);
         LLVMSetVisibility(value, LLVMVisibility::LLVMDefaultVisibility);
     }
 }
 
-pub extern "C" fn diagnostic_handler(info: LLVMDiagnosticInfoRef, _data: *mut c_void) {
-    let message = unsafe { CStr::from_ptr(LLVMGetDiagInfoDescription(info)) };
-    let message_s = message.to_str().unwrap();
+/// Logs the diagnostics reported by LLVM. `data` can point to a `Diagnostics` the diagnostics are
+/// reported to.
+pub extern "C" fn diagnostic_handler(info: LLVMDiagnosticInfoRef, data: *mut c_void) {
+    let message = Message {
+        ptr: unsafe { LLVMGetDiagInfoDescription(info) },
+    };
+    let message_s = message.to_string();
 
     use llvm_sys::LLVMDiagnosticSeverity::*;
-    match unsafe { LLVMGetDiagInfoSeverity(info) } {
-        LLVMDSError => error!("llvm: {}", message_s),
-        LLVMDSWarning => warn!("llvm: {}", message_s),
-        LLVMDSRemark => debug!("remark: {}", message_s),
-        LLVMDSNote => debug!("note: {}", message_s),
+    let severity = match unsafe { LLVMGetDiagInfoSeverity(info) } {
+        LLVMDSError => {
+            error!("llvm: {}", message_s);
+            Severity::Error
+        }
+        LLVMDSWarning => {
+            warn!("llvm: {}", message_s);
+            Severity::Warning
+        }
+        LLVMDSRemark => {
+            debug!("remark: {}", message_s);
+            Severity::Remark
+        }
+        LLVMDSNote => {
+            debug!("note: {}", message_s);
+            Severity::Note
+        }
     };
+
+    if !data.is_null() {
+        let diagnostics = unsafe { &*(data as *const Diagnostics) };
+        diagnostics.report(severity, &message_s);
+    }
 }
 
+thread_local! {
+    // diagnostics of the linker running on this thread, LLVM doesn't give fatal_error() a context
+    static FATAL_ERROR_DIAGNOSTICS: Cell<*const Diagnostics> = const { Cell::new(ptr::null()) };
+}
+
+/// Makes `fatal_error` report to `diagnostics`, which must be valid until
+/// `clear_fatal_error_diagnostics` is called.
+pub fn set_fatal_error_diagnostics(diagnostics: &Diagnostics) {
+    FATAL_ERROR_DIAGNOSTICS.with(|d| d.set(diagnostics));
+}
+
+pub fn clear_fatal_error_diagnostics(diagnostics: &Diagnostics) {
+    FATAL_ERROR_DIAGNOSTICS.with(|d| {
+        if ptr::eq(d.get(), diagnostics) {
+            d.set(ptr::null());
+        }
+    });
+}
+
+/// Reports a fatal error and exits the process, since LLVM aborts if this returns.
 pub extern "C" fn fatal_error(reason: *const c_char) {
-    error!("fatal error: {:?}", unsafe { CStr::from_ptr(reason) })
+    let reason = unsafe { CStr::from_ptr(reason) }.to_string_lossy();
+    let diagnostics = FATAL_ERROR_DIAGNOSTICS.with(Cell::get);
+    let message = match unsafe { diagnostics.as_ref() } {
+        Some(diagnostics) => diagnostics.fatal(&reason),
+        None => format!("LLVM fatal error: {reason}"),
+    };
+
+    error!("{}", message);
+    // the process is about to exit, make sure the log is complete
+    logger().flush();
+    eprintln!("error: {message}");
+    process::exit(LLVM_FATAL_EXIT_CODE);
 }
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    analysis::BPF_INSN_SIZE,
+    elf::{Object, SHF_ALLOC, SHF_EXECINSTR, STB_LOCAL, STT_FILE, STT_SECTION},
+};
+
+/// Writes the map of `object`: the size of its allocated sections, then for every symbol its
+/// section, size, linkage and the input it came from according to `origins`.
+pub(crate) fn write_map(
+    object: &Object,
+    origins: &HashMap<String, PathBuf>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let insns = |index: usize, size: u64| {
+        if object.sections[index].flags & SHF_EXECINSTR != 0 {
+            (size / BPF_INSN_SIZE).to_string()
+        } else {
+            "-".to_string()
+        }
+    };
+
+    let sections = (1..object.sections.len())
+        .filter(|index| object.sections[*index].flags & SHF_ALLOC != 0)
+        .collect::<Vec<_>>();
+    let mut symbols = object
+        .symbols
+        .iter()
+        .skip(1)
+        .filter(|symbol| !matches!(symbol.kind(), STT_SECTION | STT_FILE))
+        .collect::<Vec<_>>();
+    // undefined symbols last
+    symbols.sort_by_key(|symbol| (symbol.section_index().unwrap_or(usize::MAX), symbol.value));
+
+    let section_name = |index: Option<usize>| match index {
+        Some(index) => object.sections[index].name.as_str(),
+        None => "*UND*",
+    };
+    let section_width = sections
+        .iter()
+        .map(|index| section_name(Some(*index)).len())
+        .chain(
+            symbols
+                .iter()
+                .map(|s| section_name(s.section_index()).len()),
+        )
+        .chain(["Section".len()])
+        .max()
+        .unwrap();
+    let symbol_width = symbols
+        .iter()
+        .map(|symbol| symbol.name.len())
+        .chain(["Symbol".len()])
+        .max()
+        .unwrap();
+
+    writeln!(out, "Sections:")?;
+    writeln!(
+        out,
+        "{:section_width$} {:>8} {:>6}",
+        "Section", "Size", "Insns"
+    )?;
+    for index in sections {
+        let section = &object.sections[index];
+        writeln!(
+            out,
+            "{:section_width$} {:>8} {:>6}",
+            section.name,
+            section.size,
+            insns(index, section.size)
+        )?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Symbols:")?;
+    writeln!(
+        out,
+        "{:section_width$} {:>8} {:>6} {:8} {:symbol_width$} Origin",
+        "Section", "Size", "Insns", "Linkage", "Symbol"
+    )?;
+    for symbol in symbols {
+        let linkage = if symbol.binding() == STB_LOCAL {
+            "internal"
+        } else {
+            "exported"
+        };
+        let origin = origins
+            .get(&symbol.name)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        writeln!(
+            out,
+            "{:section_width$} {:>8} {:>6} {:8} {:symbol_width$} {}",
+            section_name(symbol.section_index()),
+            symbol.size,
+            match symbol.section_index() {
+                Some(index) => insns(index, symbol.size),
+                None => "-".to_string(),
+            },
+            linkage,
+            symbol.name,
+            origin
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // see tests/inputs/sections.ll
+    const SECTIONS: &[u8] = include_bytes!("../tests/inputs/sections.o");
+
+    #[test]
+    fn test_write_map() {
+        let object = Object::parse(SECTIONS).unwrap();
+        let origins = ["prog", "lookup", "MAP"]
+            .iter()
+            .map(|name| (name.to_string(), PathBuf::from("sections.ll")))
+            .chain([("unused".to_string(), PathBuf::from("libfoo.a(foo.o)"))])
+            .collect();
+        let mut map = Vec::new();
+        write_map(&object, &origins, &mut map).unwrap();
+        let map = String::from_utf8(map).unwrap();
+        let line = |prefix: &str, symbol: &str| {
+            map.lines()
+                .find(|line| {
+                    line.starts_with(prefix) && line.split_whitespace().nth(4) == Some(symbol)
+                })
+                .unwrap_or_else(|| panic!("{symbol} not found in\n{map}"))
+                .split_whitespace()
+                .collect::<Vec<_>>()
+        };
+
+        let prog = line("xdp ", "prog");
+        assert_eq!(prog[3..], ["exported", "prog", "sections.ll"]);
+        let size = prog[1].parse::<u64>().unwrap();
+        assert_eq!(prog[2], (size / 8).to_string());
+
+        assert_eq!(
+            line(".text ", "lookup")[3..],
+            ["internal", "lookup", "sections.ll"]
+        );
+        assert_eq!(
+            line("classifier/unused ", "unused")[3..],
+            ["internal", "unused", "libfoo.a(foo.o)"]
+        );
+        // data has no instructions
+        assert_eq!(
+            line("maps ", "MAP")[1..],
+            ["16", "-", "exported", "MAP", "sections.ll"]
+        );
+        assert_eq!(
+            line(".rodata ", "TABLE")[2..],
+            ["-", "internal", "TABLE", "-"]
+        );
+
+        // the sections come first
+        assert!(map.starts_with("Sections:\n"));
+        let xdp = map
+            .lines()
+            .find(|line| line.starts_with("xdp "))
+            .unwrap()
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        assert_eq!(xdp.len(), 3);
+        assert!(!map.contains(".debug_info"));
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashSet;
+
+use crate::{
+    analysis,
+    elf::{Object, EM_BPF, SHF_ALLOC, STB_LOCAL, STT_FUNC},
+};
+
+/// `BTF_KIND_DATASEC`, the BTF type describing the variables of a section.
+const BTF_KIND_DATASEC: u32 = 15;
+
+/// Removes the DWARF sections. Line info is removed from `.BTF.ext` before generating code.
+pub(crate) fn strip_debug(object: &mut Object) {
+    object.remove_sections(|section| {
+        section.flags & SHF_ALLOC == 0 && section.name.starts_with(".debug")
+    });
+}
+
+/// Removes the symbols loaders don't need. Global symbols, which include the programs, the
+/// symbols of the functions programs call, which loaders use to find BPF to BPF calls, symbols in
+/// map sections and sections described by BTF, and the symbols relocations refer to are kept.
+pub(crate) fn strip_symbols(object: &mut Object) {
+    let called = if object.machine == EM_BPF {
+        analysis::called_functions(object)
+    } else {
+        // calls can only be followed in BPF code, keep every function
+        object
+            .symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| symbol.kind() == STT_FUNC)
+            .map(|(index, _)| index)
+            .collect()
+    };
+    let referenced = object
+        .sections
+        .iter()
+        .flat_map(|section| section.relocations.iter().map(|r| r.symbol as usize))
+        .collect::<HashSet<_>>();
+    let described = btf_sections(object);
+    let keep_section = object
+        .sections
+        .iter()
+        .map(|section| is_map_section(&section.name) || described.contains(&section.name))
+        .collect::<Vec<_>>();
+
+    object.retain_symbols(|index, symbol| {
+        symbol.binding() != STB_LOCAL
+            || called.contains(&index)
+            || referenced.contains(&index)
+            || matches!(symbol.section_index(), Some(section) if keep_section[section])
+    });
+}
+
+/// Removes the allocated sections that aren't reachable from the sections defining global
+/// symbols, the license and version sections and the sections described by BTF, and their
+/// records in `.BTF.ext`. Returns the names of the removed sections.
+pub(crate) fn gc_sections(object: &mut Object) -> Vec<String> {
+    let described = btf_sections(object);
+    let mut live = object
+        .sections
+        .iter()
+        .map(|section| {
+            section.flags & SHF_ALLOC == 0
+                || matches!(section.name.as_str(), "license" | "version")
+                || described.contains(&section.name)
+        })
+        .collect::<Vec<_>>();
+    for symbol in &object.symbols {
+        if let Some(index) = symbol.section_index() {
+            if symbol.binding() != STB_LOCAL {
+                live[index] = true;
+            }
+        }
+    }
+
+    // section -> sections its relocations refer to. Relocations in non allocated sections, like
+    // the debug info, don't keep anything alive.
+    let mut references = vec![Vec::new(); object.sections.len()];
+    for section in &object.sections {
+        if let Some(target) = section.relocated_section() {
+            if object.sections[target].flags & SHF_ALLOC == 0 {
+                continue;
+            }
+            references[target].extend(
+                section
+                    .relocations
+                    .iter()
+                    .filter_map(|r| object.symbols.get(r.symbol as usize)?.section_index()),
+            );
+        }
+    }
+
+    let mut stack = (0..live.len())
+        .filter(|index| live[*index] && object.sections[*index].flags & SHF_ALLOC != 0)
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        for &referenced in &references[index] {
+            if !live[referenced] {
+                live[referenced] = true;
+                stack.push(referenced);
+            }
+        }
+    }
+
+    let removed = object
+        .sections
+        .iter()
+        .enumerate()
+        .filter(|(index, section)| !live[*index] && section.relocated_section().is_none())
+        .map(|(_, section)| section.name.clone())
+        .collect::<HashSet<_>>();
+    remove_btf_ext_records(object, &removed);
+    object.remove_sections(|section| removed.contains(&section.name))
+}
+
+// removes the func info, line info and CO-RE relocation records of the sections in `removed`
+// from `.BTF.ext`, loaders reject records about sections that don't exist
+fn remove_btf_ext_records(object: &mut Object, removed: &HashSet<String>) {
+    let little_endian = object.little_endian;
+    let (btf, ext) = match (
+        object.sections.iter().position(|s| s.name == ".BTF"),
+        object.sections.iter().position(|s| s.name == ".BTF.ext"),
+    ) {
+        (Some(btf), Some(ext)) => (btf, ext),
+        _ => return,
+    };
+    let btf = &object.sections[btf].data;
+    let rewritten = rewrite_btf_ext(&object.sections[ext].data, little_endian, |name_off| {
+        btf_string(btf, little_endian, name_off).is_some_and(|name| removed.contains(&name))
+    });
+    let (data, spans) = match rewritten {
+        Some(rewritten) => rewritten,
+        None => return,
+    };
+
+    let section = &mut object.sections[ext];
+    section.size = data.len() as u64;
+    section.data = data;
+    // the relocations of the records that are kept move with them
+    for section in &mut object.sections {
+        if section.relocated_section() == Some(ext) {
+            section.relocations.retain_mut(|relocation| {
+                match spans
+                    .iter()
+                    .find(|(start, end, _)| (*start..*end).contains(&relocation.offset))
+                {
+                    Some((start, _, new_start)) => {
+                        relocation.offset = new_start + (relocation.offset - start);
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+    }
+}
+
+// a span of `.BTF.ext` that is kept when removing records: `(start, end, new start)`
+type Span = (u64, u64, u64);
+
+// rewrites the `.BTF.ext` section `data` without the records of the sections whose name offset
+// `remove` returns true for. Returns the new data, and the spans of the old data that are kept.
+fn rewrite_btf_ext(
+    data: &[u8],
+    little_endian: bool,
+    mut remove: impl FnMut(u32) -> bool,
+) -> Option<(Vec<u8>, Vec<Span>)> {
+    let read = |offset: usize| read_u32(data, offset, little_endian);
+    let hdr_len = read(4)? as usize;
+    let mut ext = data.get(..hdr_len)?.to_vec();
+    let mut spans = Vec::new();
+    let mut keep = |ext: &mut Vec<u8>, start: usize, end: usize| -> Option<()> {
+        spans.push((start as u64, end as u64, ext.len() as u64));
+        ext.extend_from_slice(data.get(start..end)?);
+        Some(())
+    };
+
+    // func_info, line_info, and the CO-RE relocations when the header is long enough for them.
+    // Each starts with the size of its records, followed by the records of each section:
+    // the section name offset, the number of records and the records.
+    for header in (8..hdr_len.min(32)).step_by(8) {
+        let start = hdr_len.checked_add(read(header)? as usize)?;
+        let end = start.checked_add(read(header + 4)? as usize)?;
+        let new_start = ext.len();
+        if end > start {
+            let record_size = read(start)? as usize;
+            keep(&mut ext, start, start + 4)?;
+            let mut offset = start + 4;
+            while offset < end {
+                let name_off = read(offset)?;
+                let records = read(offset + 4)? as usize;
+                let next = records
+                    .checked_mul(record_size)
+                    .and_then(|size| size.checked_add(offset + 8))?;
+                if !remove(name_off) {
+                    keep(&mut ext, offset, next)?;
+                }
+                offset = next;
+            }
+        }
+        let (offset, len) = ((new_start - hdr_len) as u32, (ext.len() - new_start) as u32);
+        write_u32(&mut ext, header, offset, little_endian)?;
+        write_u32(&mut ext, header + 4, len, little_endian)?;
+    }
+
+    Some((ext, spans))
+}
+
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32, little_endian: bool) -> Option<()> {
+    let bytes = if little_endian {
+        value.to_le_bytes()
+    } else {
+        value.to_be_bytes()
+    };
+    data.get_mut(offset..offset.checked_add(4)?)?
+        .copy_from_slice(&bytes);
+    Some(())
+}
+
+// returns the string at `name_off` in the string section of the `.BTF` section `btf`
+fn btf_string(btf: &[u8], little_endian: bool, name_off: u32) -> Option<String> {
+    let read = |offset: usize| read_u32(btf, offset, little_endian);
+    let strings = (read(4)? as usize)
+        .checked_add(read(16)? as usize)?
+        .checked_add(name_off as usize)?;
+    let name = btf.get(strings..)?;
+    let len = name.iter().position(|b| *b == 0)?;
+    Some(String::from_utf8_lossy(&name[..len]).into_owned())
+}
+
+// aya and libbpf put legacy maps in `maps` and `maps/<name>`, BTF maps in `.maps`
+fn is_map_section(name: &str) -> bool {
+    name == "maps" || name.starts_with("maps/") || name == ".maps"
+}
+
+// returns the names of the sections BTF describes with a DATASEC type. libbpf fails to load
+// objects missing those sections or the symbols of their variables.
+fn btf_sections(object: &Object) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let btf = match object
+        .sections
+        .iter()
+        .find(|section| section.name == ".BTF")
+    {
+        Some(section) => &section.data,
+        None => return names,
+    };
+    let read = |offset: usize| -> Option<u32> {
+        let bytes = btf.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if object.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let header = || -> Option<(usize, usize, usize)> {
+        let hdr_len = read(4)? as usize;
+        Some((
+            hdr_len + read(8)? as usize,
+            read(12)? as usize,
+            hdr_len + read(16)? as usize,
+        ))
+    };
+    let (types, types_len, strings) = match header() {
+        Some(header) => header,
+        None => return names,
+    };
+
+    let mut offset = types;
+    while offset + 12 <= types + types_len {
+        let (name_off, info) = match (read(offset), read(offset + 4)) {
+            (Some(name_off), Some(info)) => (name_off as usize, info),
+            _ => break,
+        };
+        let kind = (info >> 24) & 0x1f;
+        let vlen = (info & 0xffff) as usize;
+        if kind == BTF_KIND_DATASEC {
+            if let Some(name) = btf.get(strings + name_off..) {
+                let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+                names.insert(String::from_utf8_lossy(&name[..len]).into_owned());
+            }
+        }
+        // the size of the data following each kind of type
+        offset += 12
+            + match kind {
+                // INT, VAR, DECL_TAG
+                1 | 14 | 17 => 4,
+                // ARRAY
+                3 => 12,
+                // STRUCT, UNION, DATASEC, ENUM64
+                4 | 5 | 15 | 19 => vlen * 12,
+                // ENUM, FUNC_PROTO
+                6 | 13 => vlen * 8,
+                // PTR, FWD, TYPEDEF, VOLATILE, CONST, RESTRICT, FUNC, FLOAT, TYPE_TAG
+                2 | 7..=12 | 16 | 18 => 0,
+                // a kind added after this was written, the types that follow can't be parsed
+                _ => break,
+            };
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // see tests/inputs/sections.ll
+    const SECTIONS: &[u8] = include_bytes!("../tests/inputs/sections.o");
+
+    fn section_names(object: &Object) -> Vec<&str> {
+        object.sections.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    // section symbols are named after their section
+    fn symbol_names(object: &Object) -> Vec<&str> {
+        object
+            .symbols
+            .iter()
+            .map(|s| match s.section_index() {
+                Some(index) if s.name.is_empty() => object.sections[index].name.as_str(),
+                _ => s.name.as_str(),
+            })
+            .collect()
+    }
+
+    // returns the names of the sections `.BTF.ext` has records for
+    fn btf_ext_sections(object: &Object) -> HashSet<String> {
+        let section = |name: &str| {
+            &object
+                .sections
+                .iter()
+                .find(|section| section.name == name)
+                .unwrap()
+                .data
+        };
+        let btf = section(".BTF");
+        let mut names = HashSet::new();
+        rewrite_btf_ext(section(".BTF.ext"), object.little_endian, |name_off| {
+            names.extend(btf_string(btf, object.little_endian, name_off));
+            false
+        })
+        .unwrap();
+        names
+    }
+
+    #[test]
+    fn test_malformed_btf_ext() {
+        // a header too short for the lengths of its func_info records, which are read past it
+        let mut data = vec![0; 16];
+        data[4] = 12;
+        assert!(rewrite_btf_ext(&data, true, |_| false).is_none());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let object = Object::parse(SECTIONS).unwrap();
+        let written = Object::parse(&object.write()).unwrap();
+        assert_eq!(section_names(&object), section_names(&written));
+        assert_eq!(symbol_names(&object), symbol_names(&written));
+        // the string and symbol tables are rebuilt, everything else is the same
+        for (before, after) in object.sections.iter().zip(&written.sections) {
+            if ![".strtab", ".symtab"].contains(&before.name.as_str()) {
+                assert_eq!(before.data, after.data, "{}", before.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strip_debug() {
+        let mut object = Object::parse(SECTIONS).unwrap();
+        strip_debug(&mut object);
+        let object = Object::parse(&object.write()).unwrap();
+        let sections = section_names(&object);
+        assert!(!sections.iter().any(|name| name.contains(".debug")));
+        assert!(sections.contains(&".BTF"));
+        assert!(sections.contains(&".rel.BTF.ext"));
+        assert!(!symbol_names(&object).contains(&".debug_info"));
+    }
+
+    #[test]
+    fn test_strip_symbols() {
+        let mut object = Object::parse(SECTIONS).unwrap();
+        strip_debug(&mut object);
+        strip_symbols(&mut object);
+        let object = Object::parse(&object.write()).unwrap();
+        // `lookup` is called by `prog`, `unused` isn't called and isn't exported
+        assert_eq!(
+            symbol_names(&object),
+            [
+                "",
+                ".text",
+                "xdp",
+                "lookup",
+                "classifier/unused",
+                ".rodata",
+                ".rodata.unused",
+                "DESCRIBED",
+                ".data.described",
+                "prog",
+                "MAP",
+                "_license"
+            ]
+        );
+        // relocations follow the symbols
+        let relocations = &object
+            .sections
+            .iter()
+            .find(|s| s.name == ".relxdp")
+            .unwrap()
+            .relocations;
+        let targets = relocations
+            .iter()
+            .map(|r| symbol_names(&object)[r.symbol as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(targets, [".text", "MAP"]);
+    }
+
+    #[test]
+    fn test_gc_sections() {
+        let mut object = Object::parse(SECTIONS).unwrap();
+        assert!(btf_ext_sections(&object).contains("classifier/unused"));
+        let removed = gc_sections(&mut object);
+        assert_eq!(
+            removed,
+            ["classifier/unused", ".rodata.unused", ".eh_frame"]
+        );
+        let object = Object::parse(&object.write()).unwrap();
+        let sections = section_names(&object);
+        for name in [
+            ".text",
+            "xdp",
+            "maps",
+            "license",
+            ".rodata",
+            ".data.described",
+        ] {
+            assert!(sections.contains(&name), "{name}");
+        }
+        assert!(!sections.contains(&".relclassifier/unused"));
+        assert!(!symbol_names(&object).contains(&"UNUSED_TABLE"));
+        // so are the BTF records of the removed sections
+        assert!(btf_ext_sections(&object).contains(".text"));
+        assert!(!btf_ext_sections(&object).contains("classifier/unused"));
+        let ext = object
+            .sections
+            .iter()
+            .find(|section| section.name == ".BTF.ext")
+            .unwrap();
+        let relocations = &object
+            .sections
+            .iter()
+            .find(|section| section.name == ".rel.BTF.ext")
+            .unwrap()
+            .relocations;
+        assert!(relocations.iter().all(|r| r.offset + 4 <= ext.size));
+        // debug info relocations against removed sections are dropped
+        assert!(object.sections.iter().all(|section| section
+            .relocations
+            .iter()
+            .all(|r| r.symbol == 0 || object.symbols[r.symbol as usize].name != "unused")));
+    }
+}
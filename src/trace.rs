@@ -0,0 +1,103 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rustc_demangle::try_demangle;
+
+/// Returns whether `symbol` is `name`, either as is or demangled. Demangled names can be given
+/// with or without the hash rustc appends to them.
+pub(crate) fn symbol_matches(symbol: &str, name: &str) -> bool {
+    if symbol == name {
+        return true;
+    }
+    match try_demangle(symbol) {
+        Ok(demangled) => demangled.to_string() == name || format!("{demangled:#}") == name,
+        Err(_) => false,
+    }
+}
+
+/// Returns the chain of references from one of `roots` to `symbol`, or `None` if `symbol` isn't
+/// reachable from them. The chain starts with the root and ends with `symbol`.
+pub(crate) fn why_live(
+    roots: &HashSet<String>,
+    references: &HashMap<String, Vec<String>>,
+    symbol: &str,
+) -> Option<Vec<String>> {
+    // sort the roots so that the chain found is the same on every run
+    let mut roots = roots.iter().collect::<Vec<_>>();
+    roots.sort();
+
+    // breadth first, so the chain found is the shortest one
+    let mut parents: HashMap<&str, Option<&str>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for root in roots {
+        parents.insert(root.as_str(), None);
+        queue.push_back(root.as_str());
+    }
+    while let Some(current) = queue.pop_front() {
+        if current == symbol {
+            let mut chain = vec![current.to_string()];
+            let mut parent = parents[current];
+            while let Some(symbol) = parent {
+                chain.push(symbol.to_string());
+                parent = parents[symbol];
+            }
+            chain.reverse();
+            return Some(chain);
+        }
+        for referenced in references.get(current).into_iter().flatten() {
+            if !parents.contains_key(referenced.as_str()) {
+                parents.insert(referenced.as_str(), Some(current));
+                queue.push_back(referenced.as_str());
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_symbol_matches() {
+        let mangled = "_ZN7program4xdp417h0123456789abcdefE";
+        assert!(symbol_matches(mangled, mangled));
+        assert!(symbol_matches(mangled, "program::xdp4"));
+        assert!(symbol_matches(mangled, "program::xdp4::h0123456789abcdef"));
+        assert!(!symbol_matches(mangled, "program::xdp"));
+        assert!(symbol_matches("prog", "prog"));
+        assert!(!symbol_matches("prog", "program"));
+    }
+
+    #[test]
+    fn test_why_live() {
+        let references = [
+            ("prog", vec!["lookup", "MAP"]),
+            ("lookup", vec!["TABLE", "helper"]),
+            ("helper", vec!["lookup"]),
+            ("other", vec!["helper"]),
+        ]
+        .into_iter()
+        .map(|(symbol, refs)| {
+            (
+                symbol.to_string(),
+                refs.into_iter().map(String::from).collect(),
+            )
+        })
+        .collect();
+        let roots = ["prog".to_string(), "MAP".to_string()]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            why_live(&roots, &references, "TABLE").unwrap(),
+            ["prog", "lookup", "TABLE"]
+        );
+        assert_eq!(
+            why_live(&roots, &references, "helper").unwrap(),
+            ["prog", "lookup", "helper"]
+        );
+        assert_eq!(why_live(&roots, &references, "MAP").unwrap(), ["MAP"]);
+        assert_eq!(why_live(&roots, &references, "other"), None);
+    }
+}
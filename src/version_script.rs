@@ -0,0 +1,248 @@
+/// The symbols listed in a GNU ld version script. Only symbol visibility is supported, version
+/// tags and the dependencies between versions are accepted and ignored.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct VersionScript {
+    /// Names and globs to export.
+    pub global: Vec<String>,
+    /// Names and globs to keep local. `*` makes everything that isn't global local.
+    pub local: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Quoted(&'a str),
+    Punct(char),
+}
+
+/// Parses a version script. Errors come with the line they were found on, starting from 1.
+pub(crate) fn parse(script: &str) -> Result<VersionScript, (usize, String)> {
+    let tokens = tokenize(script)?;
+    let mut tokens = tokens.iter().peekable();
+    let mut version_script = VersionScript::default();
+
+    // version nodes: `[tag] { ... } [dependency];`
+    while let Some((line, token)) = tokens.next() {
+        let (line, token) = match token {
+            Token::Word(_) => match tokens.next() {
+                Some((line, token)) => (*line, token),
+                None => return Err((*line, "expected `{` after version tag".to_string())),
+            },
+            _ => (*line, token),
+        };
+        if *token != Token::Punct('{') {
+            return Err((line, format!("expected `{{`, found {}", describe(token))));
+        }
+
+        let mut global = true;
+        loop {
+            let (line, token) = match tokens.next() {
+                Some((line, token)) => (*line, token),
+                None => return Err((last_line(script), "unterminated version node".to_string())),
+            };
+            let name = match token {
+                Token::Punct('}') => break,
+                Token::Word(word) if is_scope(word, tokens.peek()) => {
+                    tokens.next();
+                    global = *word == "global";
+                    continue;
+                }
+                Token::Word("extern") => {
+                    return Err((line, "`extern` blocks aren't supported".to_string()))
+                }
+                Token::Word(name) | Token::Quoted(name) => name,
+                Token::Punct(_) => {
+                    return Err((
+                        line,
+                        format!("expected a symbol, found {}", describe(token)),
+                    ))
+                }
+            };
+            match tokens.next() {
+                Some((_, Token::Punct(';'))) => {}
+                // the `;` after the last symbol is optional
+                Some((_, Token::Punct('}'))) => {
+                    push(&mut version_script, global, name);
+                    break;
+                }
+                Some((line, token)) => {
+                    return Err((*line, format!("expected `;`, found {}", describe(token))))
+                }
+                None => return Err((line, "expected `;` after symbol".to_string())),
+            }
+            push(&mut version_script, global, name);
+        }
+
+        // the versions this one depends on
+        let mut line = line;
+        if let Some((dep_line, Token::Word(_))) = tokens.peek() {
+            line = *dep_line;
+            tokens.next();
+        }
+        match tokens.next() {
+            Some((_, Token::Punct(';'))) => {}
+            Some((line, token)) => {
+                return Err((*line, format!("expected `;`, found {}", describe(token))))
+            }
+            None => return Err((line, "expected `;` after version node".to_string())),
+        }
+    }
+
+    Ok(version_script)
+}
+
+fn push(version_script: &mut VersionScript, global: bool, name: &str) {
+    if global {
+        version_script.global.push(name.to_string());
+    } else {
+        version_script.local.push(name.to_string());
+    }
+}
+
+// `global` and `local` are only scopes when followed by `:`, otherwise they're symbol names
+fn is_scope(word: &str, next: Option<&&(usize, Token)>) -> bool {
+    matches!(word, "global" | "local") && matches!(next, Some((_, Token::Punct(':'))))
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("`{word}`"),
+        Token::Quoted(name) => format!("\"{name}\""),
+        Token::Punct(c) => format!("`{c}`"),
+    }
+}
+
+fn last_line(script: &str) -> usize {
+    script.lines().count().max(1)
+}
+
+fn tokenize(script: &str) -> Result<Vec<(usize, Token)>, (usize, String)> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut rest = script;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\n' => {
+                line += 1;
+                rest = &rest[1..];
+            }
+            c if c.is_whitespace() => rest = &rest[c.len_utf8()..],
+            '#' => rest = rest.find('\n').map(|end| &rest[end..]).unwrap_or(""),
+            '/' if rest.starts_with("/*") => {
+                let end = rest[2..]
+                    .find("*/")
+                    .ok_or((line, "unterminated comment".to_string()))?;
+                line += rest[..end + 2].matches('\n').count();
+                rest = &rest[end + 4..];
+            }
+            '{' | '}' | ';' | ':' => {
+                tokens.push((line, Token::Punct(c)));
+                rest = &rest[1..];
+            }
+            '"' => {
+                let quoted = &rest[1..];
+                let end = match quoted.find(['"', '\n']) {
+                    Some(end) if quoted[end..].starts_with('"') => end,
+                    _ => return Err((line, "unterminated string".to_string())),
+                };
+                tokens.push((line, Token::Quoted(&quoted[..end])));
+                rest = &quoted[end + 1..];
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "{};:\"#".contains(c))
+                    .unwrap_or(rest.len());
+                tokens.push((line, Token::Word(&rest[..end])));
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_anonymous() {
+        let script = parse("{ global: prog_*; MAP; local: *; };").unwrap();
+        assert_eq!(script.global, names(&["prog_*", "MAP"]));
+        assert_eq!(script.local, names(&["*"]));
+    }
+
+    #[test]
+    fn test_versions() {
+        let script = parse(
+            r#"
+            # programs
+            VERS_1.0 {
+                global:
+                    xdp_pass;
+                    "tc_ingress"; /* quoted names
+                                     are exact */
+                local:
+                    helper_*
+            };
+            VERS_2.0 {
+                xdp_drop;
+            } VERS_1.0;
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            script.global,
+            names(&["xdp_pass", "tc_ingress", "xdp_drop"])
+        );
+        assert_eq!(script.local, names(&["helper_*"]));
+    }
+
+    #[test]
+    fn test_scope_names() {
+        // `global` and `local` without `:` are symbols
+        let script = parse("{ global; local: local; };").unwrap();
+        assert_eq!(script.global, names(&["global"]));
+        assert_eq!(script.local, names(&["local"]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse("{\n  global:\n    prog\n    MAP;\n};").unwrap_err(),
+            (4, "expected `;`, found `MAP`".to_string())
+        );
+        assert_eq!(
+            parse("{ global: prog; }\n").unwrap_err(),
+            (1, "expected `;` after version node".to_string())
+        );
+        assert_eq!(
+            parse("VERS_1.0 {\n  global: prog;\n").unwrap_err(),
+            (2, "unterminated version node".to_string())
+        );
+        assert_eq!(
+            parse("VERS_1.0 global: prog; };").unwrap_err(),
+            (1, "expected `{`, found `global`".to_string())
+        );
+        assert_eq!(
+            parse("/* comment\n*/\n{ global: : };").unwrap_err(),
+            (3, "expected a symbol, found `:`".to_string())
+        );
+        assert_eq!(
+            parse("{\n  extern \"C++\" { ns::*; };\n};").unwrap_err(),
+            (2, "`extern` blocks aren't supported".to_string())
+        );
+        assert_eq!(
+            parse("{ \"prog;\n};").unwrap_err(),
+            (1, "unterminated string".to_string())
+        );
+        assert_eq!(
+            parse("{ prog; }; /* comment").unwrap_err(),
+            (1, "unterminated comment".to_string())
+        );
+    }
+}
@@ -0,0 +1,1177 @@
+// Check that the linker can extract bitcode from the supported input formats. The fixtures in
+// tests/inputs embed the bitcode of:
+//
+//     define i32 @fixture() {
+//       ret i32 42
+//     }
+//
+// The Mach-O objects were generated with `llc -filetype=obj -mtriple=<arch>-apple-macosx` from a
+// module that puts the bitcode in `@llvm.embedded.object` in the `__LLVM,__bitcode` section, the
+// same way clang and rustc embed bitcode. macho-universal.o contains the x86_64 and arm64
+// objects. fixture.ll is the textual IR itself.
+//
+// fat-lto.o is an ELF object with the bitcode in the `.llvm.lto` section, like the objects built
+// by clang with `-ffat-lto-objects`. The llvmbc-*.o objects have a `.llvmbc` section with the
+// SHF_COMPRESSED flag, compressed with zlib, zstd and an unknown compression type (3).
+//
+// The archive tests wrap fat-lto.o in archives nested in each other.
+//
+// duplicate.ll defines `fixture` again, to check that conflicting definitions are reported.
+//
+// btf.ll has debug info for `fixture`. In btf-variant-part.ll `fixture` returns an
+// `Option<u32>`, described with the `DW_TAG_variant_part` rustc emits for enums with data
+// variants, which gets rewritten into a union.
+//
+// btf-host.ll is btf.ll for the x86_64 host target, like the modules rustc emits when it has
+// no BPF support.
+//
+// sections.ll exports `prog` in the `xdp` section, which calls `lookup` in `.text`. `unused` in
+// `classifier/unused` is kept by `llvm.used` but not exported, and it's the only user of
+// `.rodata.unused`. sections.o is sections.ll compiled with llc, used by the unit tests.
+//
+// In stack.ll `prog` has a 256 byte frame and calls `helper`, which has a 304 byte frame and calls
+// `leaf`. `counter` loops before calling `leaf`. stack.o is stack.ll compiled with llc, used by
+// the unit tests.
+//
+// btf-maps.ll has a `.maps` global, another global, a local variable and a call, to check
+// which debug info each `--debuginfo` level keeps.
+//
+// In calls.ll `walk` and `visit` call each other, `dispatch` calls the function a global points
+// to and `helper` calls a BPF helper through its constant address.
+//
+// The library test uses the library instead of running bpf-linker, to check what the diagnostic
+// handler, the errors and the report writer receive.
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use bpf_linker::{
+    DebugInfo, Diagnostic, DiagnosticHandler, Linker, LinkerError, LinkerOptions, OptLevel,
+    OutputType, Phase, ReportWriter, Severity,
+};
+
+fn run_linker(input: &str, output: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "llvm-ir", "--export", "fixture", "-o"])
+        .arg(output)
+        .arg(PathBuf::from("tests/inputs").join(input))
+        .output()
+        .unwrap()
+}
+
+// runs bpf-linker with `args` on `input`, writing the output to a temporary file. Returns the
+// result and what was written.
+fn run(args: &[&str], input: impl AsRef<Path>) -> (Output, Vec<u8>) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let output = env::temp_dir().join(format!(
+        "bpf-linker-{}-{}.out",
+        COUNT.fetch_add(1, Ordering::Relaxed),
+        std::process::id()
+    ));
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(args)
+        .arg("-o")
+        .arg(&output)
+        .arg(input.as_ref())
+        .output()
+        .unwrap();
+    let written = fs::read(&output).unwrap_or_default();
+    let _ = fs::remove_file(&output);
+
+    (result, written)
+}
+
+// like `run`, but panics if linking fails. Returns the result and what was written.
+fn run_ok(args: &[&str], input: impl AsRef<Path>) -> (Output, Vec<u8>) {
+    let (result, written) = run(args, input);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    (result, written)
+}
+
+fn link(input: &str) -> String {
+    let output = env::temp_dir().join(format!("bpf-linker-{}-{}.ll", input, std::process::id()));
+    let result = run_linker(input, &output);
+    assert!(
+        result.status.success(),
+        "linking {input} failed: {}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let ir = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    ir
+}
+
+#[test]
+fn macho_x86_64() {
+    assert!(link("macho-x86_64.o").contains("@fixture("));
+}
+
+#[test]
+fn macho_arm64() {
+    assert!(link("macho-arm64.o").contains("@fixture("));
+}
+
+#[test]
+fn macho_i386() {
+    assert!(link("macho-i386.o").contains("@fixture("));
+}
+
+#[test]
+fn macho_universal() {
+    assert!(link("macho-universal.o").contains("@fixture("));
+}
+
+#[test]
+fn llvm_ir() {
+    assert!(link("fixture.ll").contains("@fixture("));
+}
+
+#[test]
+fn llvm_ir_parse_error() {
+    let output = env::temp_dir().join(format!("bpf-linker-invalid-{}.ll", std::process::id()));
+    let result = run_linker("invalid.ll", &output);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    // the error points at the line and column of the undefined value
+    assert!(stderr.contains("invalid.ll:2:11:"), "{stderr}");
+}
+
+#[test]
+fn fat_lto() {
+    assert!(link("fat-lto.o").contains("@fixture("));
+}
+
+#[test]
+fn compressed_zlib() {
+    assert!(link("llvmbc-zlib.o").contains("@fixture("));
+}
+
+#[test]
+fn compressed_zstd() {
+    assert!(link("llvmbc-zstd.o").contains("@fixture("));
+}
+
+#[test]
+fn compressed_unsupported() {
+    let output = env::temp_dir().join(format!("bpf-linker-unsupported-{}.ll", std::process::id()));
+    let result = run_linker("llvmbc-unsupported.o", &output);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("section `.llvmbc` uses unsupported compression type 3"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn duplicate_symbol() {
+    let output = env::temp_dir().join(format!("bpf-linker-duplicate-{}.ll", std::process::id()));
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "llvm-ir", "--export", "fixture", "-o"])
+        .arg(&output)
+        .args(["tests/inputs/fixture.ll", "tests/inputs/duplicate.ll"])
+        .output()
+        .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains(
+            "symbol `fixture` is defined in both tests/inputs/fixture.ll and tests/inputs/duplicate.ll"
+        ),
+        "{stderr}"
+    );
+}
+
+// returns a GNU archive without a symbol table containing `members`
+fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = b"!<arch>\n".to_vec();
+    for (name, data) in members {
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            format!("{name}/"),
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            archive.push(b'\n');
+        }
+    }
+
+    archive
+}
+
+// returns an archive containing fat-lto.o, nested in `depth` more archives
+fn nested_archive(depth: usize) -> Vec<u8> {
+    let object = fs::read("tests/inputs/fat-lto.o").unwrap();
+    let mut data = archive(&[("fixture.o", &object)]);
+    for _ in 0..depth {
+        data = archive(&[("nested.a", &data)]);
+    }
+
+    data
+}
+
+// returns the result of linking `archive` and the IR written
+fn link_archive(archive: &[u8], args: &[&str]) -> (Output, String) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let input = env::temp_dir().join(format!(
+        "bpf-linker-archive-{}-{}.a",
+        COUNT.fetch_add(1, Ordering::Relaxed),
+        std::process::id()
+    ));
+    fs::write(&input, archive).unwrap();
+    let (result, ir) = run(
+        &[&["--emit", "llvm-ir", "--export", "fixture"], args].concat(),
+        &input,
+    );
+    fs::remove_file(&input).unwrap();
+
+    (result, String::from_utf8(ir).unwrap())
+}
+
+#[test]
+fn nested_archives() {
+    for args in [&[][..], &["--whole-archive"]] {
+        // 8 is as deep as allowed
+        for depth in [1, 8] {
+            let (result, ir) = link_archive(&nested_archive(depth), args);
+            assert!(
+                result.status.success(),
+                "{}",
+                String::from_utf8_lossy(&result.stderr)
+            );
+            assert!(ir.contains("@fixture("), "{ir}");
+        }
+    }
+}
+
+#[test]
+fn archive_too_deep() {
+    for args in [&[][..], &["--whole-archive"]] {
+        let (result, _) = link_archive(&nested_archive(9), args);
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(
+            stderr.contains("(nested.a)(nested.a)(nested.a)(nested.a)(nested.a)(nested.a)(nested.a)(nested.a)(nested.a) is nested more than 8 levels deep"),
+            "{stderr}"
+        );
+    }
+}
+
+#[test]
+fn archived_llvm_ir() {
+    // textual IR is recognized by its content, archive members don't have an extension to go by
+    let ir = fs::read("tests/inputs/fixture.ll").unwrap();
+    for args in [&[][..], &["--whole-archive"]] {
+        let (result, ir) = link_archive(&archive(&[("fixture", &ir)]), args);
+        assert!(
+            result.status.success(),
+            "{}",
+            String::from_utf8_lossy(&result.stderr)
+        );
+        assert!(ir.contains("@fixture("), "{ir}");
+    }
+}
+
+#[test]
+fn archive_export_patterns() {
+    // members defining symbols that match an export pattern are linked, like exact names
+    let input = env::temp_dir().join(format!("bpf-linker-patterns-{}.a", std::process::id()));
+    fs::write(&input, nested_archive(0)).unwrap();
+    for export in ["fix*", "re:^fixture$"] {
+        let (_, ir) = run_ok(&["--emit", "llvm-ir", "--export", export], &input);
+        let ir = String::from_utf8(ir).unwrap();
+        assert!(ir.contains("@fixture("), "{export}: {ir}");
+    }
+    fs::remove_file(&input).unwrap();
+}
+
+#[test]
+fn archive_export_sections() {
+    // members defining symbols in exported sections are linked
+    let ir = fs::read("tests/inputs/sections.ll").unwrap();
+    let input = env::temp_dir().join(format!("bpf-linker-sections-{}.a", std::process::id()));
+    fs::write(&input, archive(&[("sections", &ir)])).unwrap();
+    let (_, ir) = run_ok(&["--emit", "llvm-ir", "--export-sections"], &input);
+    fs::remove_file(&input).unwrap();
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+}
+
+fn run_linker_btf(input: &str, debuginfo: &str, output: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "obj", "--debuginfo", debuginfo])
+        .args(["--export", "fixture", "-o"])
+        .arg(output)
+        .arg(PathBuf::from("tests/inputs").join(input))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn btf() {
+    let output = env::temp_dir().join(format!("bpf-linker-btf-{}.o", std::process::id()));
+    let result = run_linker_btf("btf.ll", "full", &output);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let object = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert!(object.windows(9).any(|w| w == b".BTF.ext\0"));
+}
+
+#[test]
+fn btf_variant_part() {
+    let output = env::temp_dir().join(format!("bpf-linker-variant-{}.o", std::process::id()));
+    let result = run_linker_btf("btf-variant-part.ll", "full", &output);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let object = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    // the enum is emitted as a union of its variants and discriminant, with a valid name
+    assert!(object.windows(12).any(|w| w == b"Option_u32_\0"));
+    assert!(object.windows(13).any(|w| w == b"discriminant\0"));
+}
+
+// returns the first line of `ir` containing `pattern`
+fn ir_line<'a>(ir: &'a str, pattern: &str) -> &'a str {
+    ir.lines().find(|line| line.contains(pattern)).unwrap()
+}
+
+#[test]
+fn debuginfo_none() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "none",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(!ir.contains("!dbg"));
+}
+
+#[test]
+fn debuginfo_maps() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "maps",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir_line(&ir, "@MAP =").contains("!dbg"));
+    assert!(!ir_line(&ir, "@COUNTER =").contains("!dbg"));
+    assert!(!ir_line(&ir, "ret i32").contains("!dbg"));
+    // functions don't have debug info
+    assert!(!ir_line(&ir, "@fixture(").contains("!dbg"));
+    assert!(!ir.contains("FixtureRet"));
+}
+
+#[test]
+fn debuginfo_signatures() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "signatures",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir_line(&ir, "@MAP =").contains("!dbg"));
+    assert!(!ir_line(&ir, "@COUNTER =").contains("!dbg"));
+    assert!(ir_line(&ir, "@fixture(").contains("!dbg"));
+    assert!(!ir_line(&ir, "ret i32").contains("!dbg"));
+    assert!(!ir.contains("call void @llvm.dbg"));
+    // functions that aren't exported are described as void()
+    assert!(ir_line(&ir, "@helper(").contains("!dbg"));
+    assert!(!ir_line(&ir, "name: \"helper\"").contains("line: 8"));
+}
+
+#[test]
+fn debuginfo_lines() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "lines",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(!ir_line(&ir, "@COUNTER =").contains("!dbg"));
+    assert!(ir_line(&ir, "@fixture(").contains("!dbg"));
+    assert!(ir_line(&ir, "ret i32").contains("!dbg"));
+    assert!(!ir.contains("call void @llvm.dbg"));
+}
+
+#[test]
+fn debuginfo_line_tables_only() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "line-tables-only",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(!ir_line(&ir, "@MAP =").contains("!dbg"));
+    assert!(ir_line(&ir, "@fixture(").contains("!dbg"));
+    assert!(ir_line(&ir, "ret i32").contains("!dbg"));
+    assert!(ir.contains("emissionKind: LineTablesOnly"));
+    // no types are left
+    assert!(!ir.contains("FixtureRet"));
+    assert!(!ir.contains("MapDef"));
+}
+
+fn line_info(input: &str) {
+    let output = env::temp_dir().join(format!("bpf-linker-{}-{}.o", input, std::process::id()));
+    let result = run_linker_btf(input, "line-tables-only", &output);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let object = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert!(object.windows(9).any(|w| w == b".BTF.ext\0"));
+}
+
+#[test]
+fn line_info_bpf_target() {
+    line_info("btf.ll");
+}
+
+#[test]
+fn line_info_host_target() {
+    line_info("btf-host.ll");
+}
+
+#[test]
+fn host_target() {
+    // modules built for the host are configured for BPF before optimization
+    let ir = link("btf-host.ll");
+    assert!(ir_line(&ir, "target triple").contains("\"bpf"), "{ir}");
+    assert!(
+        !ir_line(&ir, "target datalayout").contains("f80:128"),
+        "{ir}"
+    );
+}
+
+#[test]
+fn debuginfo_full() {
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--debuginfo",
+            "full",
+            "--export",
+            "fixture,MAP,COUNTER",
+        ],
+        "tests/inputs/btf-maps.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir_line(&ir, "@COUNTER =").contains("!dbg"));
+}
+
+fn contains(object: &[u8], s: &[u8]) -> bool {
+    object.windows(s.len()).any(|w| w == s)
+}
+
+#[test]
+fn gc_sections() {
+    let (_, object) = run_ok(
+        &["--emit", "obj", "--export", "prog,MAP,_license"],
+        "tests/inputs/sections.ll",
+    );
+    assert!(contains(&object, b"classifier/unused"));
+    assert!(contains(&object, b".rodata.unused"));
+
+    let (_, object) = run_ok(
+        &[
+            "--emit",
+            "obj",
+            "--export",
+            "prog,MAP,_license",
+            "--gc-sections",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    assert!(!contains(&object, b"classifier/unused"));
+    assert!(!contains(&object, b".rodata.unused"));
+    assert!(contains(&object, b"xdp\0"));
+    assert!(contains(&object, b"license\0"));
+}
+
+#[test]
+fn strip_debug() {
+    let (_, object) = run_ok(
+        &["--emit", "obj", "--export", "prog,MAP,_license", "--btf"],
+        "tests/inputs/sections.ll",
+    );
+    assert!(contains(&object, b".debug_info"));
+
+    let (_, object) = run_ok(
+        &[
+            "--emit",
+            "obj",
+            "--export",
+            "prog,MAP,_license",
+            "--btf",
+            "--strip-debug",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    assert!(!contains(&object, b".debug_"));
+    assert!(contains(&object, b".BTF\0"));
+}
+
+#[test]
+fn strip_all() {
+    let (_, object) = run_ok(
+        &[
+            "--emit",
+            "obj",
+            "--export",
+            "prog,MAP,_license",
+            "--btf",
+            "--strip-all",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    assert!(!contains(&object, b".debug_"));
+    // functions and maps are kept, the unused constant isn't
+    assert!(contains(&object, b"lookup\0"));
+    assert!(contains(&object, b"MAP\0"));
+    assert!(!contains(&object, b"UNUSED_TABLE"));
+}
+
+#[test]
+fn export_patterns() {
+    let (result, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--export",
+            "pro?,re:^(MAP|_license)$,tc_*",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+    assert!(ir.contains("@_license = global"), "{ir}");
+    assert!(
+        stderr.contains("export pattern `tc_*` didn't match any symbol"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn export_invalid_pattern() {
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "llvm-ir", "--export", "re:(", "-o"])
+        .arg(env::temp_dir().join(format!("bpf-linker-invalid-{}.ll", std::process::id())))
+        .arg("tests/inputs/sections.ll")
+        .output()
+        .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("invalid export pattern `re:(`"), "{stderr}");
+}
+
+#[test]
+fn export_symbols_file() {
+    let path = env::temp_dir().join(format!("bpf-linker-exports-{}.txt", std::process::id()));
+    fs::write(&path, "# programs\npr*\n\n# maps\nre:^MAP$\n").unwrap();
+    let (result, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--export-symbols",
+            path.to_str().unwrap(),
+        ],
+        "tests/inputs/sections.ll",
+    );
+    fs::remove_file(&path).unwrap();
+    let ir = String::from_utf8(ir).unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+    assert!(!stderr.contains("didn't match"), "{stderr}");
+}
+
+#[test]
+fn export_sections() {
+    let (_, ir) = run_ok(&["--emit", "llvm-ir"], "tests/inputs/sections.ll");
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(!ir.contains("define i32 @prog("), "{ir}");
+
+    let (result, ir) = run_ok(
+        &["--emit", "llvm-ir", "--export-sections=my_section"],
+        "tests/inputs/sections.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+    assert!(ir.contains("@_license = global"), "{ir}");
+    // exact section names aren't reported, like exact symbol names
+    assert!(!stderr.contains("my_section"), "{stderr}");
+}
+
+#[test]
+fn version_script() {
+    let path = env::temp_dir().join(format!(
+        "bpf-linker-version-script-{}.map",
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        "VERS_1.0 {\n  global:\n    pr*;\n    MAP;\n  local:\n    *;\n};\n",
+    )
+    .unwrap();
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--version-script",
+            path.to_str().unwrap(),
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+
+    // local globs win over global globs
+    fs::write(&path, "{\n  global:\n    *;\n  local:\n    pr?g;\n};\n").unwrap();
+    let (_, ir) = run_ok(
+        &[
+            "--emit",
+            "llvm-ir",
+            "--version-script",
+            path.to_str().unwrap(),
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let ir = String::from_utf8(ir).unwrap();
+    assert!(!ir.contains("define i32 @prog("), "{ir}");
+    assert!(ir.contains("@MAP = global"), "{ir}");
+
+    fs::write(&path, "{\n  global:\n    prog\n    MAP;\n};\n").unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "llvm-ir", "--version-script"])
+        .arg(&path)
+        .arg("-o")
+        .arg(env::temp_dir().join(format!("bpf-linker-vs-{}.ll", std::process::id())))
+        .arg("tests/inputs/sections.ll")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains(":4: invalid version script: expected `;`, found `MAP`"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn map_file() {
+    let map = env::temp_dir().join(format!("bpf-linker-map-{}.map", std::process::id()));
+    let output = env::temp_dir().join(format!("bpf-linker-map-{}.o", std::process::id()));
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--export", "prog,MAP,_license", "--print-map"])
+        .arg(format!("-Map={}", map.display()))
+        .arg("-o")
+        .arg(&output)
+        .arg("tests/inputs/sections.ll")
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let printed = String::from_utf8(result.stdout).unwrap();
+    let written = fs::read_to_string(&map).unwrap();
+    fs::remove_file(&map).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(printed, written);
+
+    let prog = written
+        .lines()
+        .find(|line| line.contains(" prog "))
+        .unwrap_or_else(|| panic!("{written}"));
+    assert!(prog.starts_with("xdp "), "{prog}");
+    assert!(prog.contains(" exported "), "{prog}");
+    assert!(prog.ends_with("tests/inputs/sections.ll"), "{prog}");
+}
+
+#[test]
+fn map_file_renamed_symbol() {
+    // LLVM renames the internal `value` of the first input when the second one defines an
+    // external `value`, the map still says where each comes from
+    let first = env::temp_dir().join(format!("bpf-linker-internal-{}.ll", std::process::id()));
+    let second = env::temp_dir().join(format!("bpf-linker-external-{}.ll", std::process::id()));
+    fs::write(
+        &first,
+        "target triple = \"bpfel\"\n\
+         define internal i32 @value(ptr %p) noinline {\n  %v = load volatile i32, ptr %p\n  ret i32 %v\n}\n\
+         define i32 @fixture(ptr %p) {\n  %v = call i32 @value(ptr %p)\n  ret i32 %v\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        &second,
+        "target triple = \"bpfel\"\ndefine i32 @value(ptr %p) {\n  ret i32 2\n}\n",
+    )
+    .unwrap();
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "fixture,value",
+            "--print-map",
+            first.to_str().unwrap(),
+        ],
+        &second,
+    );
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+    let map = String::from_utf8(result.stdout).unwrap();
+
+    let line = |symbol: &str| {
+        map.lines()
+            .find(|line| line.contains(&format!(" {symbol} ")))
+            .unwrap_or_else(|| panic!("{map}"))
+    };
+    assert!(line("value").ends_with(&*second.to_string_lossy()), "{map}");
+    assert!(
+        line("value.1").ends_with(&*first.to_string_lossy()),
+        "{map}"
+    );
+}
+
+#[test]
+fn trace_symbol() {
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "prog,MAP,_license",
+            "--trace-symbol",
+            "lookup",
+            "--trace-symbol=MAP",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains("tests/inputs/sections.ll: definition of lookup"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("tests/inputs/sections.ll: definition of MAP"),
+        "{stdout}"
+    );
+
+    // archive members that aren't linked are reported too
+    let archive = env::temp_dir().join(format!("bpf-linker-trace-{}.a", std::process::id()));
+    fs::write(&archive, nested_archive(0)).unwrap();
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "prog,MAP,_license",
+            "--trace-symbol=fixture",
+            archive.to_str().unwrap(),
+        ],
+        "tests/inputs/sections.ll",
+    );
+    fs::remove_file(&archive).unwrap();
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains("(fixture.o): definition of fixture, not linked"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn why_live() {
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "prog,MAP,_license",
+            "--why-live",
+            "TABLE",
+            "--why-live=UNUSED_TABLE",
+            "--why-live=missing",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        stdout.contains("TABLE is live: prog (exported) -> lookup -> TABLE"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("UNUSED_TABLE is live: llvm.used -> unused -> UNUSED_TABLE"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("missing isn't in the output"), "{stdout}");
+}
+
+#[test]
+fn print_gc_sections() {
+    // `fixture` isn't exported
+    let output = env::temp_dir().join(format!("bpf-linker-gc-{}.ll", std::process::id()));
+    let result = Command::new(env!("CARGO_BIN_EXE_bpf-linker"))
+        .args(["--emit", "llvm-ir", "--print-gc-sections", "-o"])
+        .arg(&output)
+        .arg("tests/inputs/fixture.ll")
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    fs::remove_file(&output).unwrap();
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "removing unused function `fixture` from tests/inputs/fixture.ll\n"
+    );
+
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "prog,MAP,_license",
+            "--print-gc-sections",
+            "--gc-sections",
+        ],
+        "tests/inputs/sections.ll",
+    );
+    let stdout = String::from_utf8(result.stdout).unwrap();
+    assert!(!stdout.contains("`prog`"), "{stdout}");
+    assert!(
+        stdout.contains("removing unused section `classifier/unused`"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn stack_size_limit() {
+    let (result, _) = run(&["--export", "prog,small,counter"], "tests/inputs/stack.ll");
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("program `prog` uses 608 bytes of stack, more than the limit of 512"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("prog (256 bytes) -> helper (304 bytes) -> leaf (0 bytes)"),
+        "{stderr}"
+    );
+
+    run_ok(
+        &["--export", "prog,small,counter", "--stack-size-limit=1024"],
+        "tests/inputs/stack.ll",
+    );
+    run_ok(
+        &["--export", "prog,small,counter", "--stack-size-limit=0"],
+        "tests/inputs/stack.ll",
+    );
+}
+
+#[test]
+fn max_insns() {
+    let (result, _) = run_ok(
+        &[
+            "--export",
+            "prog,small,counter",
+            "--stack-size-limit=0",
+            "--max-insns=15",
+        ],
+        "tests/inputs/stack.ll",
+    );
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("program `counter` has 17 instructions, more than the limit of 15"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("program `prog`"), "{stderr}");
+
+    // info messages go to stdout
+    let (result, _) = run(
+        &[
+            "--export",
+            "prog,small,counter",
+            "--stack-size-limit=0",
+            "--log-level=info",
+        ],
+        "tests/inputs/stack.ll",
+    );
+    let output = format!(
+        "{}{}",
+        String::from_utf8_lossy(&result.stdout),
+        String::from_utf8_lossy(&result.stderr)
+    );
+    assert!(
+        output.contains("program `counter`: 17 instructions, 2 branches, 1 loops"),
+        "{output}"
+    );
+    assert!(!output.contains("more than the limit"), "{output}");
+}
+
+#[test]
+fn kernel_version() {
+    // 4.14 doesn't support calls, so `leaf` gets inlined, nor loops, and the one in `counter`
+    // can't be unrolled
+    let (result, _) = run(
+        &["--export", "counter", "--kernel-version=4.14"],
+        "tests/inputs/stack.ll",
+    );
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("program `counter` uses loops, which kernel 4.14 doesn't support"),
+        "{stderr}"
+    );
+
+    run_ok(
+        &["--export", "counter", "--kernel-version=5.4"],
+        "tests/inputs/stack.ll",
+    );
+    run_ok(
+        &["--export", "small", "--kernel-version=4.19"],
+        "tests/inputs/stack.ll",
+    );
+
+    // the profile picks the processor, unless one is given, `generic` included
+    for (args, cpu) in [
+        (&["--kernel-version=5.10"][..], "v3"),
+        (&["--kernel-version=5.10", "--cpu", "generic"], "generic"),
+    ] {
+        let mut args = args.to_vec();
+        args.extend(["--export", "small", "--log-level=debug"]);
+        let (result, _) = run(&args, "tests/inputs/stack.ll");
+        let output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&result.stdout),
+            String::from_utf8_lossy(&result.stderr)
+        );
+        assert!(result.status.success(), "{output}");
+        assert!(
+            output.contains(&format!(" cpu: {cpu} features: ")),
+            "{output}"
+        );
+    }
+
+    let (result, _) = run(
+        &["--export", "counter", "--kernel-version=4.9"],
+        "tests/inputs/stack.ll",
+    );
+    assert!(!result.status.success());
+    // without calls every function is inlined, even when the inliner wouldn't otherwise
+    let input = env::temp_dir().join(format!("bpf-linker-inline-{}.ll", std::process::id()));
+    fs::write(
+        &input,
+        "target triple = \"bpfel\"\n\
+         define i32 @first(ptr %ctx) section \"xdp/first\" {\n  %v = call i32 @shared(ptr %ctx)\n  ret i32 %v\n}\n\
+         define i32 @second(ptr %ctx) section \"xdp/second\" {\n  %v = call i32 @shared(ptr %ctx)\n  ret i32 %v\n}\n\
+         define internal i32 @shared(ptr %ctx) noinline {\n  %a = load volatile i32, ptr %ctx\n  %b = load volatile i32, ptr %ctx\n  %c = add i32 %a, %b\n  ret i32 %c\n}\n",
+    )
+    .unwrap();
+    let (result, _) = run(
+        &["-O0", "--export", "first,second", "--kernel-version=4.14"],
+        &input,
+    );
+    fs::remove_file(&input).unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+}
+
+#[test]
+fn recursive_call() {
+    let (result, _) = run(&["--export", "walk"], "tests/inputs/calls.ll");
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains(
+            "recursive calls aren't supported by the verifier: `walk` -> `visit` -> `walk`"
+        ),
+        "{stderr}"
+    );
+
+    // IR doesn't go through the verifier
+    run_ok(
+        &["--emit", "llvm-ir", "--export", "walk"],
+        "tests/inputs/calls.ll",
+    );
+}
+
+#[test]
+fn indirect_call() {
+    let (result, _) = run(&["--export", "dispatch"], "tests/inputs/calls.ll");
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains(
+            "indirect calls aren't supported by the verifier: `dispatch` calls a function pointer at"
+        ),
+        "{stderr}"
+    );
+
+    // helper calls aren't indirect
+    run_ok(&["--export", "helper"], "tests/inputs/calls.ll");
+}
+
+fn linker_options(inputs: Vec<PathBuf>, output: PathBuf) -> LinkerOptions {
+    LinkerOptions {
+        target: None,
+        cpu: None,
+        cpu_features: String::new(),
+        inputs: inputs.into_iter().map(Into::into).collect(),
+        output,
+        output_type: OutputType::LlvmAssembly,
+        libs: Vec::new(),
+        optimize: OptLevel::Default,
+        export_symbols: HashSet::from(["fixture".to_string()]),
+        export_sections: None,
+        version_script: None,
+        unroll_loops: false,
+        ignore_inline_never: false,
+        dump_module: None,
+        llvm_args: Vec::new(),
+        disable_expand_memcpy_in_order: false,
+        disable_memory_builtins: false,
+        debug_info: DebugInfo::None,
+        strip_debug: false,
+        strip_all: false,
+        gc_sections: false,
+        map_file: None,
+        print_map: false,
+        stack_size_limit: None,
+        max_insns: None,
+        kernel: None,
+        print_gc_sections: false,
+        trace_symbols: Vec::new(),
+        why_live: Vec::new(),
+        diagnostic_handler: None,
+        report_writer: None,
+    }
+}
+
+// LLVM parses its command line options once per process, so a single test links with the
+// library
+#[test]
+fn library() {
+    let output = env::temp_dir().join(format!("bpf-linker-diagnostics-{}.ll", std::process::id()));
+
+    // the handler receives what LLVM reports, the error about `fixture` being defined twice
+    let handled = Rc::new(RefCell::new(Vec::<Diagnostic>::new()));
+    let mut options = linker_options(
+        vec![
+            PathBuf::from("tests/inputs/fixture.ll"),
+            PathBuf::from("tests/inputs/duplicate.ll"),
+        ],
+        output.clone(),
+    );
+    let handler = Rc::clone(&handled);
+    options.diagnostic_handler = Some(DiagnosticHandler(Box::new(move |diagnostic| {
+        handler.borrow_mut().push(diagnostic.clone())
+    })));
+    let result = Linker::new(options).link();
+    assert!(
+        matches!(result, Err(LinkerError::SymbolMultiplyDefined(..))),
+        "{result:?}"
+    );
+    let handled = handled.borrow();
+    assert!(
+        handled
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error
+                && diagnostic.phase == Phase::Link
+                && diagnostic.message.contains("'fixture'")),
+        "{handled:?}"
+    );
+
+    // errors carry the diagnostics reported while parsing the input they're about
+    let input = env::temp_dir().join(format!("bpf-linker-diagnostics-{}.bc", std::process::id()));
+    fs::write(&input, b"BC\xC0\xDEnot really bitcode").unwrap();
+    let result = Linker::new(linker_options(vec![input.clone()], output.clone())).link();
+    fs::remove_file(&input).unwrap();
+    let _ = fs::remove_file(&output);
+    match result {
+        Err(LinkerError::LinkModuleError(path, diagnostics)) => {
+            assert_eq!(path, input);
+            assert!(!diagnostics.is_empty());
+            assert!(diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.phase == Phase::Parse));
+        }
+        result => panic!("{result:?}"),
+    }
+
+    // reports go to the report writer
+    let report = env::temp_dir().join(format!("bpf-linker-report-{}.txt", std::process::id()));
+    let mut options = linker_options(
+        vec![PathBuf::from("tests/inputs/fixture.ll")],
+        output.clone(),
+    );
+    options.trace_symbols = vec!["fixture".to_string()];
+    options.report_writer = Some(ReportWriter(Box::new(fs::File::create(&report).unwrap())));
+    Linker::new(options).link().unwrap();
+    let _ = fs::remove_file(&output);
+    let written = fs::read_to_string(&report).unwrap();
+    fs::remove_file(&report).unwrap();
+    assert_eq!(written, "tests/inputs/fixture.ll: definition of fixture\n");
+}
@@ -0,0 +1,46 @@
+target triple = "bpfel"
+
+%MapDef = type { i32 }
+
+@MAP = global %MapDef zeroinitializer, section ".maps", !dbg !10
+@COUNTER = global i64 0, !dbg !15
+
+define i32 @fixture() !dbg !5 {
+  %1 = call i32 @helper(), !dbg !9
+  call void @llvm.dbg.value(metadata i32 %1, metadata !19, metadata !DIExpression()), !dbg !9
+  ret i32 %1, !dbg !9
+}
+
+define internal i32 @helper() noinline !dbg !20 {
+  ret i32 42, !dbg !21
+}
+
+declare void @llvm.dbg.value(metadata, metadata, metadata)
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !1, producer: "rustc", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, globals: !4)
+!1 = !DIFile(filename: "src/main.rs", directory: "/fixture")
+!2 = !{i32 7, !"Dwarf Version", i32 4}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = !{!10, !15}
+!5 = distinct !DISubprogram(name: "fixture", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{!8}
+!8 = !DIDerivedType(tag: DW_TAG_typedef, name: "FixtureRet", baseType: !14)
+!9 = !DILocation(line: 2, column: 5, scope: !5)
+!10 = !DIGlobalVariableExpression(var: !11, expr: !DIExpression())
+!11 = distinct !DIGlobalVariable(name: "MAP", scope: !1, file: !1, line: 5, type: !12, isLocal: false, isDefinition: true)
+!12 = !DICompositeType(tag: DW_TAG_structure_type, name: "MapDef", file: !1, size: 32, align: 32, elements: !13)
+!13 = !{!22}
+!14 = !DIBasicType(name: "i32", size: 32, encoding: DW_ATE_signed)
+!15 = !DIGlobalVariableExpression(var: !16, expr: !DIExpression())
+!16 = distinct !DIGlobalVariable(name: "COUNTER", scope: !1, file: !1, line: 6, type: !17, isLocal: false, isDefinition: true)
+!17 = !DIDerivedType(tag: DW_TAG_typedef, name: "Counter", baseType: !18)
+!18 = !DIBasicType(name: "u64", size: 64, encoding: DW_ATE_unsigned)
+!19 = !DILocalVariable(name: "value", scope: !5, file: !1, line: 2, type: !23)
+!20 = distinct !DISubprogram(name: "helper", scope: !1, file: !1, line: 8, type: !6, scopeLine: 8, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!21 = !DILocation(line: 9, column: 5, scope: !20)
+!22 = !DIDerivedType(tag: DW_TAG_member, name: "max_entries", scope: !12, file: !1, baseType: !14, size: 32, align: 32)
+!23 = !DIDerivedType(tag: DW_TAG_typedef, name: "LocalValue", baseType: !14)
@@ -0,0 +1,30 @@
+target triple = "bpfel"
+
+define i64 @fixture() !dbg !5 {
+  ret i64 0, !dbg !9
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !1, producer: "rustc", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "src/main.rs", directory: "/fixture")
+!2 = !{i32 7, !"Dwarf Version", i32 4}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!5 = distinct !DISubprogram(name: "fixture", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{!10}
+!8 = !DIBasicType(name: "u32", size: 32, encoding: DW_ATE_unsigned)
+!9 = !DILocation(line: 2, column: 5, scope: !5)
+!10 = !DICompositeType(tag: DW_TAG_structure_type, name: "Option<u32>", file: !1, size: 64, align: 32, elements: !11, identifier: "option_u32")
+!11 = !{!12}
+!12 = !DICompositeType(tag: DW_TAG_variant_part, scope: !10, file: !1, size: 64, align: 32, elements: !13, discriminator: !14)
+!13 = !{!15, !16}
+!14 = !DIDerivedType(tag: DW_TAG_member, scope: !10, file: !1, baseType: !8, size: 32, align: 32, flags: DIFlagArtificial)
+!15 = !DIDerivedType(tag: DW_TAG_member, name: "None", scope: !12, file: !1, baseType: !17, size: 64, align: 32, extraData: i32 0)
+!16 = !DIDerivedType(tag: DW_TAG_member, name: "Some", scope: !12, file: !1, baseType: !19, size: 64, align: 32, extraData: i32 1)
+!17 = !DICompositeType(tag: DW_TAG_structure_type, name: "None", scope: !10, file: !1, size: 64, align: 32, elements: !18, identifier: "option_u32_none")
+!18 = !{}
+!19 = !DICompositeType(tag: DW_TAG_structure_type, name: "Some", scope: !10, file: !1, size: 64, align: 32, elements: !20, identifier: "option_u32_some")
+!20 = !{!21}
+!21 = !DIDerivedType(tag: DW_TAG_member, name: "__0", scope: !19, file: !1, baseType: !8, size: 32, align: 32, offset: 32)
@@ -0,0 +1,37 @@
+target triple = "bpfel"
+
+@HANDLER = internal global ptr @handle, align 8
+
+define i32 @walk(ptr %ctx) noinline section "xdp/walk" {
+  %value = load volatile i32, ptr %ctx, align 4
+  %done = icmp eq i32 %value, 0
+  br i1 %done, label %exit, label %again
+
+again:
+  %next = call i32 @visit(ptr %ctx)
+  ret i32 %next
+
+exit:
+  ret i32 0
+}
+
+define internal i32 @visit(ptr %ctx) noinline {
+  %value = call i32 @walk(ptr %ctx)
+  ret i32 %value
+}
+
+define i32 @dispatch(ptr %ctx) section "xdp/dispatch" {
+  %handler = load volatile ptr, ptr @HANDLER, align 8
+  %value = call i32 %handler(ptr %ctx)
+  ret i32 %value
+}
+
+define internal i32 @handle(ptr %ctx) {
+  ret i32 1
+}
+
+define i32 @helper(ptr %ctx) section "xdp/helper" {
+  %time = call i64 inttoptr (i64 5 to ptr)()
+  %value = trunc i64 %time to i32
+  ret i32 %value
+}
@@ -0,0 +1,3 @@
+define i32 @fixture() {
+  ret i32 42
+}
@@ -0,0 +1,216 @@
+; Debug info metadata emitted by rustc 1.95 (rustc --crate-type=lib -g -C opt-level=0
+; --emit=llvm-ir) for the aya style types below, used by the tests in src/btf.rs.
+;
+;     #![no_std]
+;
+;     use core::marker::PhantomData;
+;
+;     #[repr(C)]
+;     pub struct bpf_map_def {
+;         pub type_: u32,
+;         pub key_size: u32,
+;         pub value_size: u32,
+;         pub max_entries: u32,
+;         pub map_flags: u32,
+;     }
+;
+;     #[repr(transparent)]
+;     pub struct HashMap<K, V> {
+;         def: bpf_map_def,
+;         _k: PhantomData<K>,
+;         _v: PhantomData<V>,
+;     }
+;
+;     pub struct XdpContext {
+;         pub ctx: *mut u8,
+;     }
+;
+;     pub enum Action {
+;         Pass,
+;         Drop(u32),
+;         Redirect { ifindex: u32, flags: u64 },
+;     }
+;
+;     #[no_mangle]
+;     #[link_section = "maps"]
+;     pub static mut EVENTS: HashMap<u32, u64> = HashMap {
+;         def: bpf_map_def {
+;             type_: 1,
+;             key_size: 4,
+;             value_size: 8,
+;             max_entries: 1024,
+;             map_flags: 0,
+;         },
+;         _k: PhantomData,
+;         _v: PhantomData,
+;     };
+;
+;     #[no_mangle]
+;     pub fn classify(ctx: &XdpContext, port: Option<u32>) -> Result<Action, ()> {
+;         let _unit = ();
+;         match port {
+;             Some(p) if !ctx.ctx.is_null() => Ok(Action::Drop(p)),
+;             Some(_) => Ok(Action::Redirect { ifindex: 1, flags: 0 }),
+;             None => Err(()),
+;         }
+;     }
+
+!llvm.module.flags = !{!31, !32, !33, !34}
+!llvm.ident = !{!35}
+!llvm.dbg.cu = !{!36}
+!0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())
+!1 = distinct !DIGlobalVariable(name: "EVENTS", scope: !2, file: !3, line: 33, type: !4, isLocal: false, isDefinition: true, align: 32)
+!2 = !DINamespace(name: "aya_types", scope: null)
+!3 = !DIFile(filename: "aya_types.rs", directory: "/tmp/rs", checksumkind: CSK_MD5, checksum: "bcdf064c8eb5713bf404c2f6e20a133e")
+!4 = !DICompositeType(tag: DW_TAG_structure_type, name: "HashMap<u32, u64>", scope: !2, file: !5, size: 160, align: 32, flags: DIFlagPublic, elements: !6, templateParams: !28, identifier: "f223b948f5435dc841be09c66c5a3959")
+!5 = !DIFile(filename: "<unknown>", directory: "")
+!6 = !{!7, !17, !23}
+!7 = !DIDerivedType(tag: DW_TAG_member, name: "def", scope: !4, file: !5, baseType: !8, size: 160, align: 32, flags: DIFlagPrivate)
+!8 = !DICompositeType(tag: DW_TAG_structure_type, name: "bpf_map_def", scope: !2, file: !5, size: 160, align: 32, flags: DIFlagPublic, elements: !9, templateParams: !16, identifier: "75671344d0895c2952fe0c3752b21f74")
+!9 = !{!10, !12, !13, !14, !15}
+!10 = !DIDerivedType(tag: DW_TAG_member, name: "type_", scope: !8, file: !5, baseType: !11, size: 32, align: 32, flags: DIFlagPublic)
+!11 = !DIBasicType(name: "u32", size: 32, encoding: DW_ATE_unsigned)
+!12 = !DIDerivedType(tag: DW_TAG_member, name: "key_size", scope: !8, file: !5, baseType: !11, size: 32, align: 32, offset: 32, flags: DIFlagPublic)
+!13 = !DIDerivedType(tag: DW_TAG_member, name: "value_size", scope: !8, file: !5, baseType: !11, size: 32, align: 32, offset: 64, flags: DIFlagPublic)
+!14 = !DIDerivedType(tag: DW_TAG_member, name: "max_entries", scope: !8, file: !5, baseType: !11, size: 32, align: 32, offset: 96, flags: DIFlagPublic)
+!15 = !DIDerivedType(tag: DW_TAG_member, name: "map_flags", scope: !8, file: !5, baseType: !11, size: 32, align: 32, offset: 128, flags: DIFlagPublic)
+!16 = !{}
+!17 = !DIDerivedType(tag: DW_TAG_member, name: "_k", scope: !4, file: !5, baseType: !18, align: 8, offset: 160, flags: DIFlagPrivate)
+!18 = !DICompositeType(tag: DW_TAG_structure_type, name: "PhantomData<u32>", scope: !19, file: !5, align: 8, flags: DIFlagPublic, elements: !16, templateParams: !21, identifier: "22b38a2374285c7c4395d66812ec9371")
+!19 = !DINamespace(name: "marker", scope: !20)
+!20 = !DINamespace(name: "core", scope: null)
+!21 = !{!22}
+!22 = !DITemplateTypeParameter(name: "T", type: !11)
+!23 = !DIDerivedType(tag: DW_TAG_member, name: "_v", scope: !4, file: !5, baseType: !24, align: 8, offset: 160, flags: DIFlagPrivate)
+!24 = !DICompositeType(tag: DW_TAG_structure_type, name: "PhantomData<u64>", scope: !19, file: !5, align: 8, flags: DIFlagPublic, elements: !16, templateParams: !25, identifier: "a18c0f604714e83479daf388b0b74b92")
+!25 = !{!26}
+!26 = !DITemplateTypeParameter(name: "T", type: !27)
+!27 = !DIBasicType(name: "u64", size: 64, encoding: DW_ATE_unsigned)
+!28 = !{!29, !30}
+!29 = !DITemplateTypeParameter(name: "K", type: !11)
+!30 = !DITemplateTypeParameter(name: "V", type: !27)
+!31 = !{i32 8, !"PIC Level", i32 2}
+!32 = !{i32 2, !"RtLibUseGOT", i32 1}
+!33 = !{i32 7, !"Dwarf Version", i32 4}
+!34 = !{i32 2, !"Debug Info Version", i32 3}
+!35 = !{!"rustc version 1.95.0 (59807616e 2026-04-14)"}
+!36 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !37, producer: "clang LLVM (rustc version 1.95.0 (59807616e 2026-04-14))", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug, globals: !38, splitDebugInlining: false, nameTableKind: None)
+!37 = !DIFile(filename: "aya_types.rs/@/aya_types.7210cb14fea3cc92-cgu.0", directory: "/tmp/rs")
+!38 = !{!0}
+!39 = distinct !DISubprogram(name: "is_null<u8>", linkageName: "_ZN4core3ptr7mut_ptr31_$LT$impl$u20$$BP$mut$u20$T$GT$7is_null17h98e0b1655643b71eE", scope: !41, file: !40, line: 22, type: !44, scopeLine: 22, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition, unit: !36, templateParams: !51, retainedNodes: !49)
+!40 = !DIFile(filename: "library/core/src/ptr/mut_ptr.rs", directory: "/rustc/59807616e1fa2540724bfbac14d7976d7e4a3860", checksumkind: CSK_MD5, checksum: "7c03c8e0ae695174f7f10881dc49d2cb")
+!41 = !DINamespace(name: "{impl#0}", scope: !42)
+!42 = !DINamespace(name: "mut_ptr", scope: !43)
+!43 = !DINamespace(name: "ptr", scope: !20)
+!44 = !DISubroutineType(types: !45)
+!45 = !{!46, !47}
+!46 = !DIBasicType(name: "bool", size: 8, encoding: DW_ATE_boolean)
+!47 = !DIDerivedType(tag: DW_TAG_pointer_type, name: "*mut u8", baseType: !48, size: 64, align: 64, dwarfAddressSpace: 0)
+!48 = !DIBasicType(name: "u8", size: 8, encoding: DW_ATE_unsigned)
+!49 = !{!50}
+!50 = !DILocalVariable(name: "self", arg: 1, scope: !39, file: !40, line: 22, type: !47)
+!51 = !{!52}
+!52 = !DITemplateTypeParameter(name: "T", type: !48)
+!53 = !DILocation(line: 22, column: 26, scope: !39)
+!54 = !DILocation(line: 159, column: 18, scope: !55, inlinedAt: !63)
+!55 = distinct !DISubprogram(name: "addr<u8>", linkageName: "_ZN4core3ptr9const_ptr33_$LT$impl$u20$$BP$const$u20$T$GT$4addr17h916ea97b1af3b8d6E", scope: !57, file: !56, line: 153, type: !59, scopeLine: 153, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition, unit: !36, templateParams: !51)
+!56 = !DIFile(filename: "library/core/src/ptr/const_ptr.rs", directory: "/rustc/59807616e1fa2540724bfbac14d7976d7e4a3860", checksumkind: CSK_MD5, checksum: "48638a38fe414ca30cde797e4a40b3a9")
+!57 = !DINamespace(name: "{impl#0}", scope: !58)
+!58 = !DINamespace(name: "const_ptr", scope: !43)
+!59 = !DISubroutineType(types: !60)
+!60 = !{!61, !62}
+!61 = !DIBasicType(name: "usize", size: 64, encoding: DW_ATE_unsigned)
+!62 = !DIDerivedType(tag: DW_TAG_pointer_type, name: "*const u8", baseType: !48, size: 64, align: 64, dwarfAddressSpace: 0)
+!63 = !DILocation(line: 38, column: 21, scope: !64, inlinedAt: !70)
+!64 = !DILexicalBlockFile(scope: !65, file: !56, discriminator: 0)
+!65 = distinct !DISubprogram(name: "runtime", linkageName: "_ZN4core3ptr9const_ptr33_$LT$impl$u20$$BP$const$u20$T$GT$7is_null7runtime17h4cfaad72ed66a108E", scope: !67, file: !66, line: 2434, type: !68, scopeLine: 2434, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition, unit: !36, templateParams: !16)
+!66 = !DIFile(filename: "library/core/src/intrinsics/mod.rs", directory: "/rustc/59807616e1fa2540724bfbac14d7976d7e4a3860", checksumkind: CSK_MD5, checksum: "b1b7915c1b8b57766c20270acae549ce")
+!67 = !DINamespace(name: "is_null", scope: !57)
+!68 = !DISubroutineType(types: !69)
+!69 = !{!46, !62}
+!70 = !DILocation(line: 2447, column: 9, scope: !71, inlinedAt: !73)
+!71 = !DILexicalBlockFile(scope: !72, file: !66, discriminator: 0)
+!72 = distinct !DISubprogram(name: "is_null<u8>", linkageName: "_ZN4core3ptr9const_ptr33_$LT$impl$u20$$BP$const$u20$T$GT$7is_null17h2e3848dfaf9413c2E", scope: !57, file: !56, line: 22, type: !68, scopeLine: 22, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition, unit: !36, templateParams: !51)
+!73 = !DILocation(line: 23, column: 27, scope: !39)
+!74 = !DILocation(line: 38, column: 17, scope: !64, inlinedAt: !70)
+!75 = !DILocation(line: 24, column: 6, scope: !39)
+!76 = distinct !DISubprogram(name: "classify", scope: !2, file: !3, line: 46, type: !77, scopeLine: 46, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition, unit: !36, templateParams: !16, retainedNodes: !129)
+!77 = !DISubroutineType(types: !78)
+!78 = !{!79, !113, !117}
+!79 = !DICompositeType(tag: DW_TAG_structure_type, name: "Result<aya_types::Action, ()>", scope: !80, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !81, templateParams: !16, identifier: "4c64b3b78a20d72110f1c5ca8d9bf14c")
+!80 = !DINamespace(name: "result", scope: !20)
+!81 = !{!82}
+!82 = !DICompositeType(tag: DW_TAG_variant_part, scope: !79, file: !5, size: 128, align: 64, elements: !83, templateParams: !16, identifier: "277909fe1372938f5f3ec426df4dd58c", discriminator: !112)
+!83 = !{!84, !108}
+!84 = !DIDerivedType(tag: DW_TAG_member, name: "Ok", scope: !82, file: !5, baseType: !85, size: 128, align: 64)
+!85 = !DICompositeType(tag: DW_TAG_structure_type, name: "Ok", scope: !79, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !86, templateParams: !104, identifier: "7e412e00af61d3e658e375e1a0a803a9")
+!86 = !{!87}
+!87 = !DIDerivedType(tag: DW_TAG_member, name: "__0", scope: !85, file: !5, baseType: !88, size: 128, align: 64, flags: DIFlagPublic)
+!88 = !DICompositeType(tag: DW_TAG_structure_type, name: "Action", scope: !2, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !89, templateParams: !16, identifier: "402a797a39577d4ea3bc99633e361cea")
+!89 = !{!90}
+!90 = !DICompositeType(tag: DW_TAG_variant_part, scope: !88, file: !5, size: 128, align: 64, elements: !91, templateParams: !16, identifier: "53193bb8f94e2a9b358723589576b32c", discriminator: !103)
+!91 = !{!92, !94, !98}
+!92 = !DIDerivedType(tag: DW_TAG_member, name: "Pass", scope: !90, file: !5, baseType: !93, size: 128, align: 64, extraData: i32 0)
+!93 = !DICompositeType(tag: DW_TAG_structure_type, name: "Pass", scope: !88, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !16, identifier: "d094e9a9718d848f852fc9c695286804")
+!94 = !DIDerivedType(tag: DW_TAG_member, name: "Drop", scope: !90, file: !5, baseType: !95, size: 128, align: 64, extraData: i32 1)
+!95 = !DICompositeType(tag: DW_TAG_structure_type, name: "Drop", scope: !88, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !96, templateParams: !16, identifier: "b36ebbb8a1d32938f5a2d227af45ab12")
+!96 = !{!97}
+!97 = !DIDerivedType(tag: DW_TAG_member, name: "__0", scope: !95, file: !5, baseType: !11, size: 32, align: 32, offset: 32, flags: DIFlagPublic)
+!98 = !DIDerivedType(tag: DW_TAG_member, name: "Redirect", scope: !90, file: !5, baseType: !99, size: 128, align: 64, extraData: i32 2)
+!99 = !DICompositeType(tag: DW_TAG_structure_type, name: "Redirect", scope: !88, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !100, templateParams: !16, identifier: "ef5f552909e890144e6ffbdeb194146b")
+!100 = !{!101, !102}
+!101 = !DIDerivedType(tag: DW_TAG_member, name: "ifindex", scope: !99, file: !5, baseType: !11, size: 32, align: 32, offset: 32, flags: DIFlagPublic)
+!102 = !DIDerivedType(tag: DW_TAG_member, name: "flags", scope: !99, file: !5, baseType: !27, size: 64, align: 64, offset: 64, flags: DIFlagPublic)
+!103 = !DIDerivedType(tag: DW_TAG_member, scope: !88, file: !5, baseType: !11, size: 32, align: 32, flags: DIFlagArtificial)
+!104 = !{!105, !106}
+!105 = !DITemplateTypeParameter(name: "T", type: !88)
+!106 = !DITemplateTypeParameter(name: "E", type: !107)
+!107 = !DIBasicType(name: "()", encoding: DW_ATE_unsigned)
+!108 = !DIDerivedType(tag: DW_TAG_member, name: "Err", scope: !82, file: !5, baseType: !109, size: 128, align: 64, extraData: i32 3)
+!109 = !DICompositeType(tag: DW_TAG_structure_type, name: "Err", scope: !79, file: !5, size: 128, align: 64, flags: DIFlagPublic, elements: !110, templateParams: !104, identifier: "3b4054fee2699e769b9d3a984b2050f7")
+!110 = !{!111}
+!111 = !DIDerivedType(tag: DW_TAG_member, name: "__0", scope: !109, file: !5, baseType: !107, align: 8, flags: DIFlagPublic)
+!112 = !DIDerivedType(tag: DW_TAG_member, scope: !79, file: !5, baseType: !11, size: 32, align: 32, flags: DIFlagArtificial)
+!113 = !DIDerivedType(tag: DW_TAG_pointer_type, name: "&aya_types::XdpContext", baseType: !114, size: 64, align: 64, dwarfAddressSpace: 0)
+!114 = !DICompositeType(tag: DW_TAG_structure_type, name: "XdpContext", scope: !2, file: !5, size: 64, align: 64, flags: DIFlagPublic, elements: !115, templateParams: !16, identifier: "4d1697b045dc0c757ab7af6ff4caf358")
+!115 = !{!116}
+!116 = !DIDerivedType(tag: DW_TAG_member, name: "ctx", scope: !114, file: !5, baseType: !47, size: 64, align: 64, flags: DIFlagPublic)
+!117 = !DICompositeType(tag: DW_TAG_structure_type, name: "Option<u32>", scope: !118, file: !5, size: 64, align: 32, flags: DIFlagPublic, elements: !119, templateParams: !16, identifier: "6f9cf68b0721c153842fac1aca06aca0")
+!118 = !DINamespace(name: "option", scope: !20)
+!119 = !{!120}
+!120 = !DICompositeType(tag: DW_TAG_variant_part, scope: !117, file: !5, size: 64, align: 32, elements: !121, templateParams: !16, identifier: "52547bc7e29ba4c3a424c45f3b6ad9d5", discriminator: !128)
+!121 = !{!122, !124}
+!122 = !DIDerivedType(tag: DW_TAG_member, name: "None", scope: !120, file: !5, baseType: !123, size: 64, align: 32, extraData: i32 0)
+!123 = !DICompositeType(tag: DW_TAG_structure_type, name: "None", scope: !117, file: !5, size: 64, align: 32, flags: DIFlagPublic, elements: !16, templateParams: !21, identifier: "2d35c67b0b954dbe72d9ec7c545db516")
+!124 = !DIDerivedType(tag: DW_TAG_member, name: "Some", scope: !120, file: !5, baseType: !125, size: 64, align: 32, extraData: i32 1)
+!125 = !DICompositeType(tag: DW_TAG_structure_type, name: "Some", scope: !117, file: !5, size: 64, align: 32, flags: DIFlagPublic, elements: !126, templateParams: !21, identifier: "8398ebe1588ef464fb4c1616d021a5e8")
+!126 = !{!127}
+!127 = !DIDerivedType(tag: DW_TAG_member, name: "__0", scope: !125, file: !5, baseType: !11, size: 32, align: 32, offset: 32, flags: DIFlagPublic)
+!128 = !DIDerivedType(tag: DW_TAG_member, scope: !117, file: !5, baseType: !11, size: 32, align: 32, flags: DIFlagArtificial)
+!129 = !{!130, !131, !132, !134, !136}
+!130 = !DILocalVariable(name: "ctx", arg: 1, scope: !76, file: !3, line: 46, type: !113)
+!131 = !DILocalVariable(name: "port", arg: 2, scope: !76, file: !3, line: 46, type: !117)
+!132 = !DILocalVariable(name: "_unit", scope: !133, file: !3, line: 47, type: !107, align: 8)
+!133 = distinct !DILexicalBlock(scope: !76, file: !3, line: 47, column: 5)
+!134 = !DILocalVariable(name: "p", scope: !135, file: !3, line: 49, type: !11, align: 32)
+!135 = distinct !DILexicalBlock(scope: !133, file: !3, line: 49, column: 9)
+!136 = !DILocalVariable(name: "p", scope: !135, file: !3, line: 49, type: !137, align: 64)
+!137 = !DIDerivedType(tag: DW_TAG_pointer_type, name: "&u32", baseType: !11, size: 64, align: 64, dwarfAddressSpace: 0)
+!138 = !DILocation(line: 46, column: 17, scope: !76)
+!139 = !DILocation(line: 46, column: 35, scope: !76)
+!140 = !DILocation(line: 47, column: 9, scope: !133)
+!141 = !DILocation(line: 48, column: 11, scope: !133)
+!142 = !DILocation(line: 48, column: 5, scope: !133)
+!143 = !DILocation(line: 49, column: 14, scope: !133)
+!144 = !DILocation(line: 49, column: 14, scope: !135)
+!145 = !DILocation(line: 49, column: 21, scope: !133)
+!146 = !DILocation(line: 49, column: 29, scope: !133)
+!147 = !DILocation(line: 51, column: 17, scope: !133)
+!148 = !DILocation(line: 51, column: 23, scope: !133)
+!149 = !DILocation(line: 53, column: 2, scope: !76)
+!150 = !DILocation(line: 49, column: 45, scope: !135)
+!151 = !DILocation(line: 49, column: 42, scope: !135)
+!152 = !DILocation(line: 49, column: 60, scope: !133)
+!153 = !DILocation(line: 50, column: 23, scope: !133)
+!154 = !DILocation(line: 50, column: 20, scope: !133)
+!155 = !DILocation(line: 50, column: 64, scope: !133)
@@ -0,0 +1,47 @@
+target triple = "bpfel"
+
+@MAP = global [4 x i32] zeroinitializer, section "maps", align 4
+@_license = global [4 x i8] c"GPL\00", section "license", align 1
+@TABLE = internal constant [4 x i32] [i32 1, i32 2, i32 3, i32 4], align 4
+@UNUSED_TABLE = internal constant [2 x i64] [i64 5, i64 6], section ".rodata.unused", align 8
+@DESCRIBED = internal global i32 0, section ".data.described", align 4, !dbg !10
+@llvm.used = appending global [2 x ptr] [ptr @unused, ptr @DESCRIBED], section "llvm.metadata"
+
+define i32 @prog(ptr %ctx) section "xdp" !dbg !5 {
+  %index = load i32, ptr %ctx, align 4, !dbg !9
+  %value = call i32 @lookup(i32 %index), !dbg !9
+  %map = load i32, ptr @MAP, align 4, !dbg !9
+  %result = add i32 %value, %map, !dbg !9
+  ret i32 %result, !dbg !9
+}
+
+define internal i32 @lookup(i32 %index) noinline section ".text" {
+  %masked = and i32 %index, 3
+  %element = getelementptr [4 x i32], ptr @TABLE, i32 0, i32 %masked
+  %value = load i32, ptr %element, align 4
+  ret i32 %value
+}
+
+define internal i64 @unused(ptr %ctx) section "classifier/unused" {
+  %index = load i32, ptr %ctx, align 4
+  %masked = and i32 %index, 1
+  %element = getelementptr [2 x i64], ptr @UNUSED_TABLE, i32 0, i32 %masked
+  %value = load i64, ptr %element, align 8
+  ret i64 %value
+}
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !1, producer: "rustc", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, globals: !4)
+!1 = !DIFile(filename: "src/main.rs", directory: "/fixture")
+!2 = !{i32 7, !"Dwarf Version", i32 4}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = !{!10}
+!5 = distinct !DISubprogram(name: "prog", scope: !1, file: !1, line: 1, type: !6, scopeLine: 1, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!6 = !DISubroutineType(types: !7)
+!7 = !{!8, null}
+!8 = !DIBasicType(name: "i32", size: 32, encoding: DW_ATE_signed)
+!9 = !DILocation(line: 2, column: 5, scope: !5)
+!10 = !DIGlobalVariableExpression(var: !11, expr: !DIExpression())
+!11 = distinct !DIGlobalVariable(name: "DESCRIBED", scope: !1, file: !1, line: 5, type: !8, isLocal: true, isDefinition: true)
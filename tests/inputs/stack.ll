@@ -0,0 +1,48 @@
+target triple = "bpfel"
+
+define i32 @prog(ptr %ctx) section "xdp" {
+  %buf = alloca [256 x i8], align 8
+  store volatile i64 1, ptr %buf, align 8
+  %value = call i32 @helper(ptr %buf)
+  ret i32 %value
+}
+
+define internal i32 @helper(ptr %data) noinline {
+  %buf = alloca [304 x i8], align 8
+  %first = load volatile i64, ptr %data, align 8
+  store volatile i64 %first, ptr %buf, align 8
+  %value = call i32 @leaf(ptr %buf)
+  ret i32 %value
+}
+
+define internal i32 @leaf(ptr %data) noinline {
+  %value = load volatile i32, ptr %data, align 4
+  ret i32 %value
+}
+
+define i32 @small(ptr %ctx) section "xdp/small" {
+  %slot = alloca i64, align 8
+  store volatile i64 1, ptr %slot, align 8
+  %value = call i32 @leaf(ptr %slot)
+  ret i32 %value
+}
+
+define i32 @counter(ptr %ctx) section "xdp/counter" {
+entry:
+  %first = load volatile i32, ptr %ctx, align 4
+  %empty = icmp eq i32 %first, 0
+  br i1 %empty, label %exit, label %loop
+
+loop:
+  %i = phi i32 [ 0, %entry ], [ %next, %loop ]
+  %value = load volatile i32, ptr %ctx, align 4
+  %next = add i32 %i, %value
+  %done = icmp ugt i32 %next, 100
+  br i1 %done, label %exit, label %loop
+
+exit:
+  %result = phi i32 [ 0, %entry ], [ %next, %loop ]
+  %leaf = call i32 @leaf(ptr %ctx)
+  %sum = add i32 %result, %leaf
+  ret i32 %sum
+}